@@ -1,6 +1,7 @@
 use crate::compiler::value::Value;
 use crate::vm::opcode::Opcode;
-use std::fmt::{Display, Formatter};
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
 
 #[derive(Debug, Clone)]
 pub struct Chunk {
@@ -21,18 +22,20 @@ impl Chunk {
     }
 
     pub fn write(&mut self, opcode: Opcode, line: usize) {
-        self.lines.push(line);
-        self.write_byte(opcode as u8);
+        self.write_byte(opcode as u8, line);
     }
 
-    pub fn write_byte(&mut self, byte: u8) {
+    pub fn write_byte(&mut self, byte: u8, line: usize) {
         self.code.push(byte);
+        self.lines.push(line);
     }
 
-    pub fn add_constant(&mut self, value: Value) -> u8 {
-        // TODO: u8 or usize?
+    /// Adds a constant and returns its index, or `None` once the chunk
+    /// holds as many constants as a byte operand can address.
+    pub fn add_constant(&mut self, value: Value) -> Option<u8> {
+        let index = u8::try_from(self.constants.len()).ok()?;
         self.constants.push(value);
-        self.constants.len() as u8 - 1
+        Some(index)
     }
 
     pub fn name_mut(&mut self) -> &mut Option<String> {
@@ -47,6 +50,11 @@ impl Chunk {
         &mut self.code
     }
 
+    /// The source line the byte at `offset` was compiled from.
+    pub fn line(&self, offset: usize) -> usize {
+        self.lines[offset]
+    }
+
     pub fn read_constant(&self, index: usize) -> &Value {
         &self.constants[index]
     }
@@ -56,35 +64,41 @@ impl Chunk {
     }
 }
 
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::new()
+    }
+}
+
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         if let Some(name) = &self.name {
             writeln!(f, "== <{}> chunk ==", name)?;
         } else {
-            writeln!(f, "== chunk ==")?;
+            writeln!(f, "== <script> chunk ==")?;
         }
 
         let mut offset = 0;
         while offset < self.code.len() {
-            offset = disassemble_instruction(f, self, &mut offset);
+            offset = disassemble_instruction(f, self, &mut offset)?;
         }
 
-        writeln!(f, "")
+        writeln!(f)
     }
 }
 
-fn disassemble_instruction(f: &mut Formatter<'_>, chunk: &Chunk, offset: &mut usize) -> usize {
-    write!(f, "{:04X}", offset);
-
-    // TODO:
-    // if *offset > 0 &&
-    //     chunk.lines[*offset] == chunk.lines[*offset - 1] {
-    //     write!(f, "   | ");
-    // } else {
-    //     write!(f, "{:4} ", chunk.lines[*offset]);
-    // }
+fn disassemble_instruction(
+    f: &mut Formatter<'_>,
+    chunk: &Chunk,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    write!(f, "{:04X} ", offset)?;
 
-    write!(f, "   | ");
+    if *offset > 0 && chunk.lines[*offset] == chunk.lines[*offset - 1] {
+        write!(f, "   | ")?;
+    } else {
+        write!(f, "{:4} ", chunk.lines[*offset])?;
+    }
 
     let instruction = Opcode::from(chunk.code[*offset]);
     match instruction {
@@ -96,7 +110,9 @@ fn disassemble_instruction(f: &mut Formatter<'_>, chunk: &Chunk, offset: &mut us
         Opcode::Divide => simple_instruction(f, "DIVIDE", offset),
         Opcode::Equal => simple_instruction(f, "EQUAL", offset),
         Opcode::Greater => simple_instruction(f, "GREATER", offset),
+        Opcode::GreaterEqual => simple_instruction(f, "GREATER_EQUAL", offset),
         Opcode::Less => simple_instruction(f, "LESS", offset),
+        Opcode::LessEqual => simple_instruction(f, "LESS_EQUAL", offset),
         Opcode::Not => simple_instruction(f, "NOT", offset),
         Opcode::Negate => simple_instruction(f, "NEGATE", offset),
         Opcode::GetLocal => byte_instruction(chunk, f, "GET_LOCAL", offset),
@@ -107,25 +123,42 @@ fn disassemble_instruction(f: &mut Formatter<'_>, chunk: &Chunk, offset: &mut us
         Opcode::Jump => jump_instruction(chunk, f, "JUMP", 1, offset),
         Opcode::JumpIfFalse => jump_instruction(chunk, f, "JUMP_IF_FALSE", 1, offset),
         Opcode::Loop => jump_instruction(chunk, f, "LOOP", 0, offset), // TODO: sign should be -1.
-        Opcode::Closure => {
-            // TODO: Clean up.
-            *offset += 2;
-
-            let constant = chunk.code[*offset - 1];
-            write!(f, "{:-16} {:4} ", "CLOSURE", constant);
-            writeln!(f, "'{:?}'", chunk.constants()[constant as usize]);
-
-            *offset
-        }
+        Opcode::Closure => closure_instruction(chunk, f, offset),
         Opcode::Call => byte_instruction(chunk, f, "CALL", offset),
         Opcode::Print => simple_instruction(f, "PRINT", offset),
         Opcode::Pop => simple_instruction(f, "POP", offset),
+        Opcode::Nil => simple_instruction(f, "NIL", offset),
+        Opcode::GetUpvalue => byte_instruction(chunk, f, "GET_UPVALUE", offset),
+        Opcode::SetUpvalue => byte_instruction(chunk, f, "SET_UPVALUE", offset),
+        Opcode::CloseUpvalue => simple_instruction(f, "CLOSE_UPVALUE", offset),
+        Opcode::Range => simple_instruction(f, "RANGE", offset),
+        Opcode::ForIter => jump_instruction(chunk, f, "FOR_ITER", 1, offset),
+        Opcode::BuildList => byte_instruction(chunk, f, "BUILD_LIST", offset),
+        Opcode::GetIndex => simple_instruction(f, "GET_INDEX", offset),
+        Opcode::SetIndex => simple_instruction(f, "SET_INDEX", offset),
+        Opcode::BuildMap => byte_instruction(chunk, f, "BUILD_MAP", offset),
+        Opcode::Class => class_instruction(chunk, f, offset),
+        Opcode::Method => constant_instruction(chunk, f, "METHOD", offset),
+        Opcode::GetProperty => constant_instruction(chunk, f, "GET_PROPERTY", offset),
+        Opcode::SetProperty => constant_instruction(chunk, f, "SET_PROPERTY", offset),
+        Opcode::Trait => trait_instruction(chunk, f, offset),
+        Opcode::Impl => simple_instruction(f, "IMPL", offset),
+        Opcode::Enum => enum_instruction(chunk, f, offset),
+        Opcode::MatchVariant => match_variant_instruction(chunk, f, offset),
+        Opcode::MatchList => byte_instruction(chunk, f, "MATCH_LIST", offset),
+        Opcode::VariantValue => byte_instruction(chunk, f, "VARIANT_VALUE", offset),
+        Opcode::NoMatch => simple_instruction(f, "NO_MATCH", offset),
+        Opcode::Interpolate => byte_instruction(chunk, f, "INTERPOLATE", offset),
     }
 }
 
-fn simple_instruction(f: &mut Formatter<'_>, name: &str, offset: &mut usize) -> usize {
-    writeln!(f, "{}", name);
-    *offset + 1
+fn simple_instruction(
+    f: &mut Formatter<'_>,
+    name: &str,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    writeln!(f, "{}", name)?;
+    Ok(*offset + 1)
 }
 
 fn constant_instruction(
@@ -133,11 +166,152 @@ fn constant_instruction(
     f: &mut Formatter<'_>,
     name: &str,
     offset: &mut usize,
-) -> usize {
+) -> Result<usize, fmt::Error> {
     let constant = chunk.code()[*offset + 1];
-    write!(f, "{:-16} {:4} ", name, constant);
-    writeln!(f, "'{}'", chunk.constants()[constant as usize]);
-    *offset + 2
+    write!(f, "{:-16} {:4} ", name, constant)?;
+    writeln!(f, "'{}'", chunk.constants()[constant as usize])?;
+    Ok(*offset + 2)
+}
+
+fn closure_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    let constant = chunk.code[*offset + 1];
+    let value = &chunk.constants()[constant as usize];
+    writeln!(f, "{:-16} {:4} '{}'", "CLOSURE", constant, value)?;
+
+    let mut next = *offset + 2;
+    if let Value::Function(fun) = value {
+        for _ in 0..fun.upvalue_count() {
+            let kind = if chunk.code[next] == 1 {
+                "local"
+            } else {
+                "upvalue"
+            };
+            writeln!(
+                f,
+                "{:04X}    |  {:>20} {}",
+                next,
+                kind,
+                chunk.code[next + 1]
+            )?;
+            next += 2;
+        }
+    }
+
+    Ok(next)
+}
+
+fn class_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    let constant = chunk.code[*offset + 1];
+    let count = chunk.code[*offset + 2] as usize;
+    writeln!(
+        f,
+        "{:-16} {:4} '{}'",
+        "CLASS",
+        constant,
+        chunk.constants()[constant as usize]
+    )?;
+
+    let mut next = *offset + 3;
+    for _ in 0..count {
+        let kind = if chunk.code[next + 1] == 1 {
+            "var"
+        } else {
+            "val"
+        };
+        let name = &chunk.constants()[chunk.code[next] as usize];
+        writeln!(f, "{:04X}    |  {:>20} {}", next, kind, name)?;
+        next += 2;
+    }
+
+    Ok(next)
+}
+
+fn trait_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    let constant = chunk.code[*offset + 1];
+    let count = chunk.code[*offset + 2] as usize;
+    writeln!(
+        f,
+        "{:-16} {:4} '{}'",
+        "TRAIT",
+        constant,
+        chunk.constants()[constant as usize]
+    )?;
+
+    let mut next = *offset + 3;
+    for _ in 0..count {
+        let name = &chunk.constants()[chunk.code[next] as usize];
+        let method = format!("{}/{}", name, chunk.code[next + 1]);
+        writeln!(f, "{:04X}    |  {:>20}", next, method)?;
+        next += 2;
+    }
+
+    Ok(next)
+}
+
+fn enum_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    let constant = chunk.code[*offset + 1];
+    let count = chunk.code[*offset + 2] as usize;
+    writeln!(
+        f,
+        "{:-16} {:4} '{}'",
+        "ENUM",
+        constant,
+        chunk.constants()[constant as usize]
+    )?;
+
+    // Each variant is its name followed by the names of its fields.
+    let mut next = *offset + 3;
+    for _ in 0..count {
+        let start = next;
+        let name = &chunk.constants()[chunk.code[next] as usize];
+        let fields: Vec<String> = (0..chunk.code[next + 1] as usize)
+            .map(|i| chunk.constants()[chunk.code[next + 2 + i] as usize].to_string())
+            .collect();
+        next += 2 + fields.len();
+
+        let variant = if fields.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}({})", name, fields.join(", "))
+        };
+        writeln!(f, "{:04X}    |  {:>20}", start, variant)?;
+    }
+
+    Ok(next)
+}
+
+fn match_variant_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
+    let constant = chunk.code[*offset + 1];
+    let count = chunk.code[*offset + 2];
+    writeln!(
+        f,
+        "{:-16} {:4} '{}' ({})",
+        "MATCH_VARIANT",
+        constant,
+        chunk.constants()[constant as usize],
+        count
+    )?;
+    Ok(*offset + 3)
 }
 
 fn jump_instruction(
@@ -146,7 +320,7 @@ fn jump_instruction(
     name: &str,
     sign: usize,
     offset: &mut usize,
-) -> usize {
+) -> Result<usize, fmt::Error> {
     let lo = chunk.code[*offset + 2] as u16;
     let hi = chunk.code[*offset + 1] as u16;
 
@@ -158,13 +332,18 @@ fn jump_instruction(
         name,
         offset,
         *offset + 3 + sign * jump as usize
-    );
+    )?;
 
-    *offset + 3
+    Ok(*offset + 3)
 }
 
-fn byte_instruction(chunk: &Chunk, f: &mut Formatter<'_>, name: &str, offset: &mut usize) -> usize {
+fn byte_instruction(
+    chunk: &Chunk,
+    f: &mut Formatter<'_>,
+    name: &str,
+    offset: &mut usize,
+) -> Result<usize, fmt::Error> {
     let slot = chunk.code[*offset + 1];
-    writeln!(f, "{:-16} {:4X}", name, slot);
-    *offset + 2
+    writeln!(f, "{:-16} {:4X}", name, slot)?;
+    Ok(*offset + 2)
 }
@@ -1,31 +1,71 @@
 use crate::compiler::chunk::Chunk;
 use crate::compiler::error::CompilerError;
-use crate::compiler::instance::CompilerInstance;
+use crate::compiler::instance::{CompilerInstance, Loop};
+use crate::compiler::local::Upvalue;
 use crate::compiler::object::{Function, FunctionType};
 use crate::compiler::value::Value;
+use crate::lexer::token::Position;
 use crate::parser::ast::Identifier;
 use crate::vm::opcode::{Opcode, StackIndex};
+use std::collections::{HashMap, HashSet};
 
 pub struct Compiler {
     current: CompilerInstance,
-    errors: Vec<CompilerError>,
+    immutable_globals: HashSet<Identifier>,
+    enums: HashMap<Identifier, Vec<Identifier>>,
+    position: Position,
+    errors: Vec<(CompilerError, Position)>,
 }
 
 impl Compiler {
     pub fn new() -> Self {
+        Compiler::with_immutable_globals(HashSet::new())
+    }
+
+    /// A compiler that already knows which globals were bound with `val`, so
+    /// a REPL can carry them from one input to the next.
+    pub fn with_immutable_globals(immutable_globals: HashSet<Identifier>) -> Self {
         Compiler {
             current: CompilerInstance::new(FunctionType::Script),
+            immutable_globals,
+            enums: HashMap::new(),
+            position: Position::default(),
             errors: vec![],
         }
     }
 
-    pub fn declare_variable(&mut self, ident: &Identifier) {
+    pub fn immutable_globals(&self) -> &HashSet<Identifier> {
+        &self.immutable_globals
+    }
+
+    /// Remembers the variants of an enum so a `match` over it can be
+    /// checked for missing arms.
+    pub fn add_enum(&mut self, ident: &Identifier, variants: Vec<Identifier>) {
+        self.enums.insert(ident.to_string(), variants);
+    }
+
+    pub fn enum_variants(&self, ident: &str) -> Option<&[Identifier]> {
+        self.enums.get(ident).map(Vec::as_slice)
+    }
+
+    pub fn declare_variable(&mut self, ident: &Identifier, mutable: bool) {
         if self.is_scoped() {
-            if self.contains_local_in_current_scope(&ident) {
+            if self.contains_local_in_current_scope(ident) {
                 self.add_error(CompilerError::LocalAlreadyDefined);
             }
 
-            self.add_local(&ident);
+            self.add_local(ident, mutable);
+        } else {
+            self.declare_global(ident, mutable);
+        }
+    }
+
+    /// Records whether the global can be assigned to.
+    pub fn declare_global(&mut self, ident: &Identifier, mutable: bool) {
+        if mutable {
+            self.immutable_globals.remove(ident);
+        } else {
+            self.immutable_globals.insert(ident.to_string());
         }
     }
 
@@ -36,9 +76,7 @@ impl Compiler {
         }
 
         self.emit(Opcode::DefineGlobal);
-        let constant_id = self
-            .current_chunk()
-            .add_constant(Value::String(ident.to_string()));
+        let constant_id = self.add_constant(Value::String(ident.to_string()));
         self.emit_byte(constant_id);
     }
 
@@ -52,8 +90,49 @@ impl Compiler {
         }
     }
 
-    pub fn add_local(&mut self, ident: &Identifier) {
-        self.current.locals_mut().insert(ident);
+    pub fn resolve_upvalue(&mut self, name: &str) -> Option<StackIndex> {
+        match self.current.resolve_upvalue(name) {
+            Ok(upvalue) => upvalue,
+            Err(error) => {
+                self.add_error(error);
+                None
+            }
+        }
+    }
+
+    pub fn upvalues(&self) -> &[Upvalue] {
+        self.current.upvalues()
+    }
+
+    /// Reports an error if the variable was bound with `val`.
+    pub fn check_assignable(&mut self, ident: &Identifier) {
+        let mutable = match self.current.local_mutability(ident) {
+            Some(mutable) => mutable,
+            None => !self.immutable_globals.contains(ident),
+        };
+
+        if !mutable {
+            self.add_error(CompilerError::AssignToImmutable(ident.to_string()));
+        }
+    }
+
+    pub fn add_local(&mut self, ident: &Identifier, mutable: bool) {
+        if self.current.locals().count() > u8::MAX as usize {
+            self.add_error(CompilerError::TooManyLocals);
+            return;
+        }
+
+        self.current.locals_mut().insert(ident, mutable);
+    }
+
+    /// Marks a value left on the stack for an instruction that consumes it
+    /// later, locals declared before then are placed above it.
+    pub fn push_temporary(&mut self) {
+        self.current.locals_mut().push_temporary();
+    }
+
+    pub fn pop_temporaries(&mut self, count: usize) {
+        self.current.locals_mut().pop_temporaries(count);
     }
 
     // TODO: Rename.
@@ -74,8 +153,85 @@ impl Compiler {
     }
 
     pub fn end_scope(&mut self) {
-        for _ in self.current.locals_mut().end_scope().iter().rev() {
-            self.emit(Opcode::Pop);
+        for local in self.current.locals_mut().end_scope().iter().rev() {
+            if local.captured() {
+                self.emit(Opcode::CloseUpvalue);
+            } else {
+                self.emit(Opcode::Pop);
+            }
+        }
+    }
+
+    /// Ends a scope holding a single local without popping it, its value
+    /// becomes the value of the expression that opened the scope.
+    pub fn end_scope_keeping_local(&mut self) {
+        self.current.locals_mut().end_scope();
+    }
+
+    /// Pops the locals of the innermost scope for code that jumps out of it.
+    /// They stay declared for the code after the jump.
+    pub fn pop_scope_locals(&mut self) {
+        let depth = self.current.locals().scope_depth();
+        self.pop_locals_deeper_than(depth - 1);
+    }
+
+    /// Starts a loop whose `continue` jumps back to `start`. Locals declared
+    /// from here on are popped by `break` and `continue`.
+    pub fn begin_loop(&mut self, start: usize) {
+        let depth = self.current.locals().scope_depth();
+        self.current.loops_mut().push(Loop::new(start, depth));
+    }
+
+    /// Ends the innermost loop, pointing its `break` jumps here.
+    pub fn end_loop(&mut self) {
+        if let Some(loop_) = self.current.loops_mut().pop() {
+            for offset in loop_.into_breaks() {
+                self.patch_jump(offset);
+            }
+        }
+    }
+
+    pub fn emit_break(&mut self) {
+        let depth = match self.current.loops_mut().last() {
+            Some(loop_) => loop_.depth(),
+            None => return self.add_error(CompilerError::BreakOutsideLoop),
+        };
+
+        self.pop_locals_deeper_than(depth);
+        let offset = self.emit_jump(Opcode::Jump);
+        if let Some(loop_) = self.current.loops_mut().last_mut() {
+            loop_.add_break(offset);
+        }
+    }
+
+    pub fn emit_continue(&mut self) {
+        let (start, depth) = match self.current.loops_mut().last() {
+            Some(loop_) => (loop_.start(), loop_.depth()),
+            None => return self.add_error(CompilerError::ContinueOutsideLoop),
+        };
+
+        self.pop_locals_deeper_than(depth);
+        self.emit_loop(start);
+    }
+
+    /// Pops the locals a jump out of their scope leaves behind. They stay
+    /// declared, the code after the jump still sees them. A local whose
+    /// initializer is being compiled has nothing on the stack yet.
+    fn pop_locals_deeper_than(&mut self, depth: usize) {
+        let captured: Vec<bool> = self
+            .current
+            .locals()
+            .deeper_than(depth)
+            .filter(|local| local.initialized())
+            .map(|local| local.captured())
+            .collect();
+
+        for captured in captured {
+            if captured {
+                self.emit(Opcode::CloseUpvalue);
+            } else {
+                self.emit(Opcode::Pop);
+            }
         }
     }
 
@@ -88,9 +244,7 @@ impl Compiler {
         self.emit_return();
         let fun_copy = self.current.function().clone();
 
-        println!("{}", self.current_chunk());
-
-        if let Some(enclosing) = *self.current.enclosing().clone() {
+        if let Some(enclosing) = self.current.enclosing().clone() {
             self.current = enclosing;
         }
 
@@ -125,21 +279,29 @@ impl Compiler {
         self.current_chunk().code_mut()[offset + 1] = (jump & 0xff) as u8;
     }
 
+    /// Records an error at the position of the expression being compiled.
     pub fn add_error(&mut self, error: CompilerError) {
-        self.errors.push(error);
+        self.errors.push((error, self.position));
+    }
+
+    pub fn errors(&self) -> &[(CompilerError, Position)] {
+        &self.errors
     }
 
     pub fn add_constant(&mut self, value: Value) -> u8 {
-        self.current_chunk().add_constant(value)
+        self.current_chunk().add_constant(value).unwrap_or_else(|| {
+            self.add_error(CompilerError::TooManyConstants);
+            0
+        })
     }
 
     pub fn emit_return(&mut self) {
-        // self.emit(Opcode::Nil); // TODO: Return Nil???
+        self.emit(Opcode::Nil);
         self.emit(Opcode::Return);
     }
 
     pub fn emit_constant(&mut self, value: Value) {
-        let constant = self.current_chunk().add_constant(value);
+        let constant = self.add_constant(value);
         self.emit(Opcode::Constant);
         self.emit_byte(constant);
     }
@@ -149,24 +311,51 @@ impl Compiler {
     }
 
     pub fn emit(&mut self, opcode: Opcode) {
-        self.current_chunk().write(opcode, 123); // TODO Line
+        let line = *self.position.line();
+        self.current_chunk().write(opcode, line);
     }
 
     pub fn emit_byte(&mut self, byte: u8) {
-        self.current_chunk().write_byte(byte);
+        let line = *self.position.line();
+        self.current_chunk().write_byte(byte, line);
+    }
+
+    /// Sets the position of the expression being compiled, returning the
+    /// previous one so it can be restored afterwards.
+    pub fn set_position(&mut self, position: Position) -> Position {
+        std::mem::replace(&mut self.position, position)
     }
 
     pub fn function_type(&self) -> &FunctionType {
-        &self.current.function_type()
+        self.current.function_type()
+    }
+
+    /// Whether the code being compiled is inside a method, possibly nested
+    /// in functions declared in the method.
+    pub fn in_method(&self) -> bool {
+        let mut instance = Some(&self.current);
+        while let Some(current) = instance {
+            if current.function_type() == &FunctionType::Method {
+                return true;
+            }
+            instance = current.enclosing().as_ref();
+        }
+        false
     }
 
     pub fn set_instance(&mut self, instance: CompilerInstance) {
         let current_copy = self.current.clone();
         self.current = instance;
-        *self.current.enclosing_mut() = Box::new(Some(current_copy));
+        **self.current.enclosing_mut() = Some(current_copy);
     }
 
     pub fn current_chunk(&mut self) -> &mut Chunk {
         self.current.function_mut().chunk_mut()
     }
 }
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
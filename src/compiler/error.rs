@@ -1,3 +1,6 @@
+use std::fmt;
+use std::fmt::Formatter;
+
 pub type CompileResult<T> = std::result::Result<T, CompilerError>;
 
 #[derive(Debug)]
@@ -5,4 +8,76 @@ pub enum CompilerError {
     LocalAlreadyDefined,
     LocalNotInitialized,
     InvalidReturn,
+    AssignToImmutable(String),
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    TooManyElements(&'static str),
+    TooManyFields,
+    TooManyConstants,
+    TooManyLocals,
+    FieldAlreadyDefined(String),
+    SelfOutsideMethod,
+    VariantAlreadyDefined(String),
+    NonExhaustiveMatch(Vec<String>),
+}
+
+impl CompilerError {
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            CompilerError::AssignToImmutable(name) => {
+                Some(format!("declare '{}' with 'var' to make it mutable", name))
+            }
+            CompilerError::LocalAlreadyDefined => {
+                Some("use 'val' or 'var' in a new block to shadow it".to_string())
+            }
+            CompilerError::NonExhaustiveMatch(_) => {
+                Some("add an arm for each missing variant or a '_' arm".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CompilerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompilerError::LocalAlreadyDefined => {
+                write!(f, "Already a variable with this name in this scope.")
+            }
+            CompilerError::LocalNotInitialized => {
+                write!(f, "Can't read local variable in its own initializer.")
+            }
+            CompilerError::InvalidReturn => write!(f, "Can't return from top-level code."),
+            CompilerError::AssignToImmutable(name) => {
+                write!(f, "Cannot assign to immutable variable '{}'.", name)
+            }
+            CompilerError::BreakOutsideLoop => write!(f, "Can't use 'break' outside of a loop."),
+            CompilerError::ContinueOutsideLoop => {
+                write!(f, "Can't use 'continue' outside of a loop.")
+            }
+            CompilerError::TooManyElements(literal) => {
+                write!(
+                    f,
+                    "Can't have more than 255 elements in a {} literal.",
+                    literal
+                )
+            }
+            CompilerError::TooManyFields => {
+                write!(f, "Can't have more than 255 fields in a class.")
+            }
+            CompilerError::TooManyConstants => write!(f, "Too many constants in one chunk."),
+            CompilerError::TooManyLocals => write!(f, "Too many local variables in function."),
+            CompilerError::FieldAlreadyDefined(name) => {
+                write!(f, "Already a field named '{}' in this class.", name)
+            }
+            CompilerError::SelfOutsideMethod => write!(f, "Can't use 'self' outside of a method."),
+            CompilerError::VariantAlreadyDefined(name) => {
+                write!(f, "Already a variant named '{}' in this enum.", name)
+            }
+            CompilerError::NonExhaustiveMatch(missing) => {
+                write!(f, "Non-exhaustive match, missing {}.", missing.join(", "))
+            }
+        }
+    }
 }
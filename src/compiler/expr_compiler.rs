@@ -4,37 +4,157 @@ use crate::compiler::instance::CompilerInstance;
 use crate::compiler::object::FunctionType;
 use crate::compiler::value::Value;
 use crate::parser::ast::{
-    BinaryOperator, BlockDecl, Expr, FunDecl, Identifier, LiteralExpr, UnaryOperator,
+    BinaryOperator, BlockDecl, ClassDecl, Expr, ExprKind, FunDecl, FunSignature, Identifier,
+    LiteralExpr, MatchArm, ModuleAst, Pattern, UnaryOperator, VariantDecl,
 };
 use crate::vm::obj::Gc;
 use crate::vm::opcode::Opcode;
+use std::collections::HashSet;
+
+/// Compiles the top-level expressions of a script. With `echo` set, a
+/// trailing expression that produces a value, other than an assignment, is
+/// returned from the script instead of discarded so a REPL can show it.
+pub fn compile_module(c: &mut Compiler, ast: ModuleAst, echo: bool) {
+    // A function can assign a global bound further down the script, so the
+    // globals bound with `val` are known before any function is compiled.
+    for expr in &ast {
+        if let ExprKind::LetAssign { ident, mutable, .. } = expr.kind() {
+            c.declare_global(ident, *mutable);
+        }
+    }
+
+    let last = ast.len().saturating_sub(1);
+    for (i, expr) in ast.into_iter().enumerate() {
+        let kind = expr.kind();
+        let assignment = matches!(
+            kind,
+            ExprKind::LetSet { .. } | ExprKind::SetIndex { .. } | ExprKind::SetProperty { .. }
+        );
+        if echo && i == last && leaves_value(kind) && !assignment {
+            compile_expr(c, expr);
+            c.emit(Opcode::Return);
+        } else {
+            compile_statement(c, expr);
+        }
+    }
+}
+
+/// Compiles an expression whose value is not used, popping it off the stack
+/// if it leaves one.
+fn compile_statement(c: &mut Compiler, expr: Expr) {
+    let pop = leaves_value(expr.kind());
+    let previous = c.set_position(*expr.position());
+    compile_expr(c, expr);
+    if pop {
+        c.emit(Opcode::Pop);
+    }
+    c.set_position(previous);
+}
+
+/// Whether compiling the expression leaves its value on the stack.
+fn leaves_value(kind: &ExprKind) -> bool {
+    matches!(
+        kind,
+        ExprKind::Grouping { .. }
+            | ExprKind::Binary { .. }
+            | ExprKind::Unary { .. }
+            | ExprKind::LetGet { .. }
+            | ExprKind::LetSet { .. }
+            | ExprKind::Call { .. }
+            | ExprKind::List { .. }
+            | ExprKind::Map { .. }
+            | ExprKind::Index { .. }
+            | ExprKind::SetIndex { .. }
+            | ExprKind::GetProperty { .. }
+            | ExprKind::SetProperty { .. }
+            | ExprKind::SelfValue
+            | ExprKind::Match { .. }
+            | ExprKind::Interpolation { .. }
+            | ExprKind::Literal(_)
+    )
+}
 
 pub fn compile_expr(c: &mut Compiler, expr: Expr) {
-    match expr {
-        Expr::Grouping { expr } => compile_expr(c, *expr),
-        Expr::Binary { left, op, right } => compile_binary(c, left, op, right),
-        Expr::Unary { op, expr } => compile_unary(c, op, expr),
-        Expr::LetAssign { ident, initializer } => compile_let_assign(c, ident, initializer),
-        Expr::LetGet { ident } => compile_let_get(c, ident),
-        Expr::LetSet { ident, expr } => compile_let_set(c, ident, expr),
-        Expr::Fun { ident, decl } => compile_function(c, ident, decl),
-        Expr::Call { callee, args } => compile_call(c, callee, args),
-        Expr::While { condition, body } => compile_while(c, condition, body),
-        Expr::IfElse {
+    let previous = c.set_position(*expr.position());
+
+    match expr.into_kind() {
+        ExprKind::Grouping { expr } => compile_expr(c, *expr),
+        ExprKind::Binary { left, op, right } => compile_binary(c, *left, op, *right),
+        ExprKind::Unary { op, expr } => compile_unary(c, op, *expr),
+        ExprKind::LetAssign {
+            ident,
+            initializer,
+            mutable,
+            ..
+        } => compile_let_assign(c, ident, *initializer, mutable),
+        ExprKind::LetGet { ident } => compile_let_get(c, ident),
+        ExprKind::LetSet { ident, expr } => compile_let_set(c, ident, *expr),
+        ExprKind::Fun { ident, decl } => compile_function(c, ident, decl),
+        ExprKind::Class { ident, decl } => compile_class(c, ident, decl),
+        ExprKind::Method { class, ident, decl } => compile_method(c, &class, ident, decl),
+        ExprKind::Trait { ident, methods } => compile_trait(c, ident, methods),
+        ExprKind::Enum {
+            ident, variants, ..
+        } => compile_enum(c, ident, variants),
+        ExprKind::Impl {
+            trait_,
+            class,
+            methods,
+        } => compile_impl(c, trait_, class, methods),
+        ExprKind::GetProperty { target, ident } => compile_get_property(c, *target, ident),
+        ExprKind::SetProperty {
+            target,
+            ident,
+            expr,
+        } => compile_set_property(c, *target, ident, *expr),
+        ExprKind::SelfValue => compile_self(c),
+        ExprKind::Call { callee, args } => compile_call(c, *callee, args),
+        ExprKind::List { elements } => compile_list(c, elements),
+        ExprKind::Interpolation { parts } => compile_interpolation(c, parts),
+        ExprKind::Map { entries } => compile_map(c, entries),
+        ExprKind::Index { target, index } => compile_index(c, *target, *index),
+        ExprKind::SetIndex {
+            target,
+            index,
+            expr,
+        } => compile_set_index(c, *target, *index, *expr),
+        ExprKind::While { condition, body } => compile_while(c, *condition, *body),
+        ExprKind::For {
+            init,
+            condition,
+            step,
+            body,
+        } => compile_for(c, init, condition, step, *body),
+        ExprKind::ForIn {
+            ident,
+            iterable,
+            body,
+        } => compile_for_in(c, ident, *iterable, *body),
+        ExprKind::IfElse {
             condition,
             then,
             else_,
-        } => compile_if_else(c, condition, then, else_),
-        Expr::Block { block } => compile_block(c, block),
-        Expr::Print { expr } => compile_print(c, expr),
-        Expr::Return { expr } => compile_return(c, expr),
-        Expr::Literal(expr) => compile_literal(c, expr),
+        } => compile_if_else(c, *condition, then, else_),
+        ExprKind::Block { block } => compile_block(c, *block),
+        ExprKind::Print { expr } => compile_print(c, *expr),
+        ExprKind::Return { expr } => compile_return(c, expr),
+        ExprKind::Match { expr, arms } => compile_match(c, *expr, arms),
+        ExprKind::Break => c.emit_break(),
+        ExprKind::Continue => c.emit_continue(),
+        ExprKind::Literal(expr) => compile_literal(c, expr),
     }
+
+    c.set_position(previous);
 }
 
-fn compile_binary(compiler: &mut Compiler, left: Box<Expr>, op: BinaryOperator, right: Box<Expr>) {
-    compile_expr(compiler, *left);
-    compile_expr(compiler, *right);
+fn compile_binary(compiler: &mut Compiler, left: Expr, op: BinaryOperator, right: Expr) {
+    match op {
+        BinaryOperator::And => return compile_and(compiler, left, right),
+        BinaryOperator::Or => return compile_or(compiler, left, right),
+        _ => {}
+    }
+
+    compile_operands(compiler, vec![left, right]);
 
     match op {
         BinaryOperator::Add => compiler.emit(Opcode::Add),
@@ -47,28 +167,63 @@ fn compile_binary(compiler: &mut Compiler, left: Box<Expr>, op: BinaryOperator,
             compiler.emit(Opcode::Not);
         }
         BinaryOperator::GreaterThan => compiler.emit(Opcode::Greater),
-        BinaryOperator::GreaterThanEqual => {
-            compiler.emit(Opcode::Less);
-            compiler.emit(Opcode::Not);
-        }
+        BinaryOperator::GreaterThanEqual => compiler.emit(Opcode::GreaterEqual),
         BinaryOperator::LessThan => compiler.emit(Opcode::Less),
-        BinaryOperator::LessThanEqual => {
-            compiler.emit(Opcode::Greater);
-            compiler.emit(Opcode::Not);
-        }
+        BinaryOperator::LessThanEqual => compiler.emit(Opcode::LessEqual),
+        BinaryOperator::Range => compiler.emit(Opcode::Range),
+        BinaryOperator::And | BinaryOperator::Or => unreachable!(),
     }
 }
 
-fn compile_unary(compiler: &mut Compiler, op: UnaryOperator, expr: Box<Expr>) {
-    compile_expr(compiler, *expr);
+/// Compiles expressions whose values stay on the stack until one
+/// instruction consumes them all. Locals declared while compiling one of
+/// them, like the bindings of a `match`, are placed above the values of the
+/// ones before it.
+fn compile_operands(compiler: &mut Compiler, operands: impl IntoIterator<Item = Expr>) {
+    let mut count = 0;
+    for operand in operands {
+        compile_expr(compiler, operand);
+        compiler.push_temporary();
+        count += 1;
+    }
+    compiler.pop_temporaries(count);
+}
+
+fn compile_and(compiler: &mut Compiler, left: Expr, right: Expr) {
+    compile_expr(compiler, left);
+
+    // A falsey left operand is the result, the right one is skipped.
+    let end_jump = compiler.emit_jump(Opcode::JumpIfFalse);
+    compiler.emit(Opcode::Pop);
+    compile_expr(compiler, right);
+
+    compiler.patch_jump(end_jump);
+}
+
+fn compile_or(compiler: &mut Compiler, left: Expr, right: Expr) {
+    compile_expr(compiler, left);
+
+    // A truthy left operand is the result, the right one is skipped.
+    let else_jump = compiler.emit_jump(Opcode::JumpIfFalse);
+    let end_jump = compiler.emit_jump(Opcode::Jump);
+
+    compiler.patch_jump(else_jump);
+    compiler.emit(Opcode::Pop);
+    compile_expr(compiler, right);
+
+    compiler.patch_jump(end_jump);
+}
+
+fn compile_unary(compiler: &mut Compiler, op: UnaryOperator, expr: Expr) {
+    compile_expr(compiler, expr);
     compiler.emit(Opcode::from(op));
 }
 
-fn compile_let_assign(compiler: &mut Compiler, ident: Identifier, init: Box<Expr>) {
-    compiler.declare_variable(&ident);
+fn compile_let_assign(compiler: &mut Compiler, ident: Identifier, init: Expr, mutable: bool) {
+    compiler.declare_variable(&ident, mutable);
 
     // Compile initializer.
-    compile_expr(compiler, *init);
+    compile_expr(compiler, init);
 
     compiler.define_variable(&ident);
 }
@@ -78,6 +233,10 @@ fn compile_let_get(compiler: &mut Compiler, ident: Identifier) {
         // Local variable
         compiler.emit(Opcode::GetLocal);
         compiler.emit_byte(local as u8);
+    } else if let Some(upvalue) = compiler.resolve_upvalue(&ident) {
+        // Variable captured from an enclosing function
+        compiler.emit(Opcode::GetUpvalue);
+        compiler.emit_byte(upvalue as u8);
     } else {
         // Global variable
         compiler.emit(Opcode::GetGlobal);
@@ -86,13 +245,18 @@ fn compile_let_get(compiler: &mut Compiler, ident: Identifier) {
     }
 }
 
-fn compile_let_set(compiler: &mut Compiler, ident: Identifier, expr: Box<Expr>) {
-    compile_expr(compiler, *expr);
+fn compile_let_set(compiler: &mut Compiler, ident: Identifier, expr: Expr) {
+    compiler.check_assignable(&ident);
+    compile_expr(compiler, expr);
 
     if let Some(local) = compiler.resolve_local(&ident) {
         // Local variable
         compiler.emit(Opcode::SetLocal);
         compiler.emit_byte(local as u8);
+    } else if let Some(upvalue) = compiler.resolve_upvalue(&ident) {
+        // Variable captured from an enclosing function
+        compiler.emit(Opcode::SetUpvalue);
+        compiler.emit_byte(upvalue as u8);
     } else {
         // Global variable
         compiler.emit(Opcode::SetGlobal);
@@ -102,6 +266,10 @@ fn compile_let_set(compiler: &mut Compiler, ident: Identifier, expr: Box<Expr>)
 }
 
 fn compile_function(compiler: &mut Compiler, ident: Identifier, decl: FunDecl) {
+    // Mark the function initialized right away so its body can refer to it.
+    compiler.declare_variable(&ident, true);
+    compiler.mark_local_initialized();
+
     compiler.set_instance(CompilerInstance::new(FunctionType::Function));
 
     compile_closure(compiler, &ident, decl);
@@ -116,63 +284,331 @@ fn compile_closure(compiler: &mut Compiler, ident: &Identifier, decl: FunDecl) {
 
     // Compile arguments.
     for arg in decl.args() {
-        compiler.declare_variable(arg);
-        compiler.define_variable(arg);
+        compiler.declare_variable(arg.ident(), true);
+        compiler.define_variable(arg.ident());
     }
 
     // Compile body.
-    compile_expr(compiler, Expr::block(decl.body()));
+    compile_expr(compiler, Expr::block(decl.into_body()));
 
     // Create the function object.
+    let upvalues = compiler.upvalues().to_vec();
     let mut fun = compiler.end_compiler();
     fun.set_name(ident.clone());
     fun.set_arity(arity as u8);
+    fun.set_upvalue_count(upvalues.len());
 
     compiler.emit(Opcode::Closure);
 
     let constant_id = compiler.add_constant(Value::Function(Gc::new(fun)));
     compiler.emit_byte(constant_id);
+
+    // Tell the VM where to find each captured variable.
+    for upvalue in upvalues {
+        compiler.emit_byte(upvalue.is_local() as u8);
+        compiler.emit_byte(upvalue.index() as u8);
+    }
 }
 
-fn compile_call(compiler: &mut Compiler, callee: Box<Expr>, args: Vec<Expr>) {
-    let arity = args.len();
+fn compile_class(compiler: &mut Compiler, ident: Identifier, decl: ClassDecl) {
+    compiler.declare_variable(&ident, false);
+
+    if decl.fields().len() > u8::MAX as usize {
+        compiler.add_error(CompilerError::TooManyFields);
+    }
+    let mut names = HashSet::new();
+    for field in decl.fields() {
+        if !names.insert(field.ident()) {
+            compiler.add_error(CompilerError::FieldAlreadyDefined(field.ident().clone()));
+        }
+    }
+
+    // The class name is followed by the name and mutability of each field.
+    let name = compiler.add_constant(Value::String(ident.clone()));
+    compiler.emit(Opcode::Class);
+    compiler.emit_byte(name);
+    compiler.emit_byte(decl.fields().len() as u8);
+    for field in decl.fields() {
+        let name = compiler.add_constant(Value::String(field.ident().clone()));
+        compiler.emit_byte(name);
+        compiler.emit_byte(field.mutable() as u8);
+    }
+
+    compiler.define_variable(&ident);
+
+    for (name, method) in decl.into_methods() {
+        compile_method(compiler, &ident, name, method);
+    }
+}
+
+fn compile_method(compiler: &mut Compiler, class: &Identifier, ident: Identifier, decl: FunDecl) {
+    compile_let_get(compiler, class.clone());
+
+    compiler.set_instance(CompilerInstance::new(FunctionType::Method));
+    compile_closure(compiler, &ident, decl);
+
+    compiler.emit(Opcode::Method);
+    let constant_id = compiler.add_constant(Value::String(ident));
+    compiler.emit_byte(constant_id);
+}
+
+fn compile_trait(compiler: &mut Compiler, ident: Identifier, methods: Vec<FunSignature>) {
+    compiler.declare_variable(&ident, false);
+
+    // The trait name is followed by the name and arity of each method.
+    let name = compiler.add_constant(Value::String(ident.clone()));
+    compiler.emit(Opcode::Trait);
+    compiler.emit_byte(name);
+    compiler.emit_byte(methods.len() as u8);
+    for method in methods {
+        let name = compiler.add_constant(Value::String(method.ident().clone()));
+        compiler.emit_byte(name);
+        compiler.emit_byte(method.args().len() as u8);
+    }
+
+    compiler.define_variable(&ident);
+}
+
+fn compile_enum(compiler: &mut Compiler, ident: Identifier, variants: Vec<VariantDecl>) {
+    compiler.declare_variable(&ident, false);
+
+    let mut names = HashSet::new();
+    for variant in &variants {
+        if !names.insert(variant.ident()) {
+            compiler.add_error(CompilerError::VariantAlreadyDefined(
+                variant.ident().clone(),
+            ));
+        }
+    }
+
+    // The enum name is followed by each variant's name, the number of
+    // values it carries and their names.
+    let name = compiler.add_constant(Value::String(ident.clone()));
+    compiler.emit(Opcode::Enum);
+    compiler.emit_byte(name);
+    compiler.emit_byte(variants.len() as u8);
+    for variant in &variants {
+        let name = compiler.add_constant(Value::String(variant.ident().clone()));
+        compiler.emit_byte(name);
+        compiler.emit_byte(variant.fields().len() as u8);
+        for field in variant.fields() {
+            let name = compiler.add_constant(Value::String(field.ident().clone()));
+            compiler.emit_byte(name);
+        }
+    }
+
+    compiler.define_variable(&ident);
+    compiler.add_enum(
+        &ident,
+        variants
+            .into_iter()
+            .map(|variant| variant.ident().clone())
+            .collect(),
+    );
+}
+
+fn compile_impl(
+    compiler: &mut Compiler,
+    trait_: Identifier,
+    class: Identifier,
+    methods: Vec<(Identifier, FunDecl)>,
+) {
+    for (name, method) in methods {
+        compile_method(compiler, &class, name, method);
+    }
+
+    // Checked once the methods are in place, they may also have been
+    // defined elsewhere.
+    compile_let_get(compiler, class);
+    compile_let_get(compiler, trait_);
+    compiler.emit(Opcode::Impl);
+}
+
+fn compile_get_property(compiler: &mut Compiler, target: Expr, ident: Identifier) {
+    compile_expr(compiler, target);
+
+    compiler.emit(Opcode::GetProperty);
+    let constant_id = compiler.add_constant(Value::String(ident));
+    compiler.emit_byte(constant_id);
+}
+
+fn compile_set_property(compiler: &mut Compiler, target: Expr, ident: Identifier, expr: Expr) {
+    compile_operands(compiler, vec![target, expr]);
+
+    compiler.emit(Opcode::SetProperty);
+    let constant_id = compiler.add_constant(Value::String(ident));
+    compiler.emit_byte(constant_id);
+}
 
-    compile_expr(compiler, *callee);
-    for arg in args {
-        compile_expr(compiler, arg);
+fn compile_self(compiler: &mut Compiler) {
+    if !compiler.in_method() {
+        compiler.add_error(CompilerError::SelfOutsideMethod);
+        return;
     }
+
+    compile_let_get(compiler, "self".to_string());
+}
+
+fn compile_call(compiler: &mut Compiler, callee: Expr, args: Vec<Expr>) {
+    let arity = args.len();
+
+    compile_operands(compiler, std::iter::once(callee).chain(args));
     compiler.emit(Opcode::Call);
     compiler.emit_byte(arity as u8);
 }
 
-fn compile_while(compiler: &mut Compiler, condition: Box<Expr>, body: Box<Expr>) {
+fn compile_list(compiler: &mut Compiler, elements: Vec<Expr>) {
+    if elements.len() > u8::MAX as usize {
+        compiler.add_error(CompilerError::TooManyElements("list"));
+    }
+
+    let count = elements.len();
+    compile_operands(compiler, elements);
+    compiler.emit(Opcode::BuildList);
+    compiler.emit_byte(count as u8);
+}
+
+fn compile_interpolation(compiler: &mut Compiler, parts: Vec<Expr>) {
+    if parts.len() > u8::MAX as usize {
+        compiler.add_error(CompilerError::TooManyElements("string"));
+    }
+
+    let count = parts.len();
+    compile_operands(compiler, parts);
+    compiler.emit(Opcode::Interpolate);
+    compiler.emit_byte(count as u8);
+}
+
+fn compile_map(compiler: &mut Compiler, entries: Vec<(Expr, Expr)>) {
+    if entries.len() > u8::MAX as usize {
+        compiler.add_error(CompilerError::TooManyElements("map"));
+    }
+
+    let count = entries.len();
+    compile_operands(
+        compiler,
+        entries
+            .into_iter()
+            .flat_map(|(key, value)| vec![key, value]),
+    );
+    compiler.emit(Opcode::BuildMap);
+    compiler.emit_byte(count as u8);
+}
+
+fn compile_index(compiler: &mut Compiler, target: Expr, index: Expr) {
+    compile_operands(compiler, vec![target, index]);
+    compiler.emit(Opcode::GetIndex);
+}
+
+fn compile_set_index(compiler: &mut Compiler, target: Expr, index: Expr, expr: Expr) {
+    compile_operands(compiler, vec![target, index, expr]);
+    compiler.emit(Opcode::SetIndex);
+}
+
+fn compile_while(compiler: &mut Compiler, condition: Expr, body: Expr) {
     let loop_start = compiler.current_chunk().code().len();
-    compile_expr(compiler, *condition);
+    compile_expr(compiler, condition);
 
     let exit_jump = compiler.emit_jump(Opcode::JumpIfFalse);
     compiler.emit(Opcode::Pop);
-    compile_expr(compiler, *body);
+    compiler.begin_loop(loop_start);
+    compile_expr(compiler, body);
 
     compiler.emit_loop(loop_start);
     compiler.patch_jump(exit_jump);
     compiler.emit(Opcode::Pop);
+    compiler.end_loop();
+}
+
+fn compile_for(
+    compiler: &mut Compiler,
+    init: Option<Box<Expr>>,
+    condition: Option<Box<Expr>>,
+    step: Option<Box<Expr>>,
+    body: Expr,
+) {
+    // Variables declared in the initializer are scoped to the loop.
+    compiler.begin_scope();
+    if let Some(init) = init {
+        compile_statement(compiler, *init);
+    }
+
+    let mut loop_start = compiler.current_chunk().code().len();
+
+    let exit_jump = condition.map(|condition| {
+        compile_expr(compiler, *condition);
+        let exit_jump = compiler.emit_jump(Opcode::JumpIfFalse);
+        compiler.emit(Opcode::Pop);
+        exit_jump
+    });
+
+    // The step comes first in the bytecode, the body jumps over it and loops
+    // back to it.
+    if let Some(step) = step {
+        let body_jump = compiler.emit_jump(Opcode::Jump);
+        let step_start = compiler.current_chunk().code().len();
+        compile_statement(compiler, *step);
+
+        compiler.emit_loop(loop_start);
+        loop_start = step_start;
+        compiler.patch_jump(body_jump);
+    }
+
+    compiler.begin_loop(loop_start);
+    compile_expr(compiler, body);
+    compiler.emit_loop(loop_start);
+
+    if let Some(exit_jump) = exit_jump {
+        compiler.patch_jump(exit_jump);
+        compiler.emit(Opcode::Pop);
+    }
+    compiler.end_loop();
+    compiler.end_scope();
+}
+
+fn compile_for_in(compiler: &mut Compiler, ident: Identifier, iterable: Expr, body: Expr) {
+    // The iterated value and the index of the next element live in hidden
+    // locals for the duration of the loop. Their names can't be written in
+    // code, so they never clash with a user's variables.
+    compiler.begin_scope();
+    compile_expr(compiler, iterable);
+    compiler.add_local(&"(iterable)".to_string(), false);
+    compiler.mark_local_initialized();
+    compiler.emit_constant(Value::Number(0.0));
+    compiler.add_local(&"(index)".to_string(), false);
+    compiler.mark_local_initialized();
+
+    let loop_start = compiler.current_chunk().code().len();
+    let exit_jump = compiler.emit_jump(Opcode::ForIter);
+
+    // Each iteration gets a fresh loop variable, so closures capture the
+    // element they were created for.
+    compiler.begin_loop(loop_start);
+    compiler.begin_scope();
+    compiler.add_local(&ident, true);
+    compiler.mark_local_initialized();
+    compile_expr(compiler, body);
+    compiler.end_scope();
+
+    compiler.emit_loop(loop_start);
+    compiler.patch_jump(exit_jump);
+    compiler.end_loop();
+    compiler.end_scope();
 }
 
 fn compile_if_else(
     compiler: &mut Compiler,
-    condition: Box<Expr>,
+    condition: Expr,
     then: BlockDecl,
     else_: Option<BlockDecl>,
 ) {
-    compile_expr(compiler, *condition);
+    compile_expr(compiler, condition);
 
     // Jump to else clause if false.
     let then_jump = compiler.emit_jump(Opcode::JumpIfFalse);
     compiler.emit(Opcode::Pop);
 
-    for expr in then {
-        compile_expr(compiler, expr);
-    }
+    compile_block(compiler, then);
 
     let else_jump = compiler.emit_jump(Opcode::Jump);
 
@@ -180,25 +616,245 @@ fn compile_if_else(
     compiler.emit(Opcode::Pop);
 
     // Compile else clause if set.
-    if let Some(exprs) = else_ {
-        for expr in exprs {
-            compile_expr(compiler, expr);
-        }
+    if let Some(block) = else_ {
+        compile_block(compiler, block);
     }
 
     compiler.patch_jump(else_jump);
 }
 
-fn compile_block(compiler: &mut Compiler, block: Box<BlockDecl>) {
+/// A step from a matched value to a value nested in it.
+#[derive(Clone)]
+enum PatternStep {
+    /// A value carried by an enum variant.
+    Value(u8),
+    /// An element of a list.
+    Element(usize),
+}
+
+/// A match is lowered to a chain of tests per arm, a failing test jumps to
+/// the next arm. When a pattern matches, its bindings are pushed as locals
+/// and the guard and body run in their scope.
+fn compile_match(compiler: &mut Compiler, expr: Expr, arms: Vec<MatchArm>) {
+    check_exhaustive(compiler, &arms);
+
+    // The matched value lives in a hidden local, which ends up holding the
+    // value of the arm that ran.
+    compiler.begin_scope();
+    compile_expr(compiler, expr);
+    let name = "(match)".to_string();
+    compiler.add_local(&name, false);
+    compiler.mark_local_initialized();
+    let slot = compiler.resolve_local(&name).unwrap_or_default() as u8;
+
+    let mut exit_jumps = vec![];
+    let mut catch_all = false;
+    for arm in arms {
+        let (pattern, guard, body) = arm.into_parts();
+        catch_all |= guard.is_none() && pattern.is_irrefutable();
+
+        // The arm's code reports errors at the line of its body, which
+        // starts on the line of the pattern.
+        let previous = compiler.set_position(*body.position());
+
+        // Failed tests jump to the next arm with their result on the stack.
+        let mut fail_jumps = vec![];
+        let mut bindings = vec![];
+        compile_pattern(
+            compiler,
+            slot,
+            pattern,
+            &mut vec![],
+            &mut fail_jumps,
+            &mut bindings,
+        );
+
+        compiler.begin_scope();
+        for (ident, path) in &bindings {
+            emit_pattern_path(compiler, slot, path);
+            compiler.declare_variable(ident, false);
+            compiler.define_variable(ident);
+        }
+
+        // A failed guard pops the bindings before moving on to the next arm.
+        let guard_jump = guard.map(|guard| {
+            compile_expr(compiler, guard);
+            let fail_jump = compiler.emit_jump(Opcode::JumpIfFalse);
+            compiler.emit(Opcode::Pop);
+            let pass_jump = compiler.emit_jump(Opcode::Jump);
+
+            compiler.patch_jump(fail_jump);
+            compiler.emit(Opcode::Pop);
+            compiler.pop_scope_locals();
+            let next_jump = compiler.emit_jump(Opcode::Jump);
+            compiler.patch_jump(pass_jump);
+            next_jump
+        });
+
+        // An arm whose body has no value gives nil.
+        if leaves_value(body.kind()) {
+            compile_expr(compiler, body);
+        } else {
+            compile_statement(compiler, body);
+            compiler.emit(Opcode::Nil);
+        }
+        compiler.emit(Opcode::SetLocal);
+        compiler.emit_byte(slot);
+        compiler.emit(Opcode::Pop);
+        compiler.end_scope();
+        exit_jumps.push(compiler.emit_jump(Opcode::Jump));
+
+        if !fail_jumps.is_empty() {
+            for jump in fail_jumps {
+                compiler.patch_jump(jump);
+            }
+            compiler.emit(Opcode::Pop);
+        }
+        if let Some(jump) = guard_jump {
+            compiler.patch_jump(jump);
+        }
+        compiler.set_position(previous);
+    }
+
+    if !catch_all {
+        compiler.emit(Opcode::GetLocal);
+        compiler.emit_byte(slot);
+        compiler.emit(Opcode::NoMatch);
+    }
+
+    for jump in exit_jumps {
+        compiler.patch_jump(jump);
+    }
+    compiler.end_scope_keeping_local();
+}
+
+/// Emits the tests for a pattern against the value at `path`, collecting
+/// the jumps taken when a test fails and the names the pattern binds.
+fn compile_pattern(
+    compiler: &mut Compiler,
+    slot: u8,
+    pattern: Pattern,
+    path: &mut Vec<PatternStep>,
+    fail_jumps: &mut Vec<usize>,
+    bindings: &mut Vec<(Identifier, Vec<PatternStep>)>,
+) {
+    match pattern {
+        Pattern::Wildcard => {}
+        Pattern::Binding(ident) => bindings.push((ident, path.clone())),
+        Pattern::Literal(literal) => {
+            emit_pattern_path(compiler, slot, path);
+            compile_literal(compiler, literal);
+            compiler.emit(Opcode::Equal);
+            fail_jumps.push(compiler.emit_jump(Opcode::JumpIfFalse));
+            compiler.emit(Opcode::Pop);
+        }
+        Pattern::Variant {
+            enum_,
+            variant,
+            fields,
+        } => {
+            emit_pattern_path(compiler, slot, path);
+            compile_let_get(compiler, enum_);
+            let name = compiler.add_constant(Value::String(variant));
+            compiler.emit(Opcode::MatchVariant);
+            compiler.emit_byte(name);
+            compiler.emit_byte(fields.len() as u8);
+            fail_jumps.push(compiler.emit_jump(Opcode::JumpIfFalse));
+            compiler.emit(Opcode::Pop);
+
+            for (i, field) in fields.into_iter().enumerate() {
+                path.push(PatternStep::Value(i as u8));
+                compile_pattern(compiler, slot, field, path, fail_jumps, bindings);
+                path.pop();
+            }
+        }
+        Pattern::List(elements) => {
+            emit_pattern_path(compiler, slot, path);
+            compiler.emit(Opcode::MatchList);
+            compiler.emit_byte(elements.len() as u8);
+            fail_jumps.push(compiler.emit_jump(Opcode::JumpIfFalse));
+            compiler.emit(Opcode::Pop);
+
+            for (i, element) in elements.into_iter().enumerate() {
+                path.push(PatternStep::Element(i));
+                compile_pattern(compiler, slot, element, path, fail_jumps, bindings);
+                path.pop();
+            }
+        }
+    }
+}
+
+/// Pushes the value nested in the matched value at `path`.
+fn emit_pattern_path(compiler: &mut Compiler, slot: u8, path: &[PatternStep]) {
+    compiler.emit(Opcode::GetLocal);
+    compiler.emit_byte(slot);
+    for step in path {
+        match step {
+            PatternStep::Value(i) => {
+                compiler.emit(Opcode::VariantValue);
+                compiler.emit_byte(*i);
+            }
+            PatternStep::Element(i) => {
+                compiler.emit_constant(Value::Number(*i as f64));
+                compiler.emit(Opcode::GetIndex);
+            }
+        }
+    }
+}
+
+/// Reports the variants a match over a known enum has no arm for. Only
+/// matches whose arms are all variants of one enum are checked, an arm with
+/// a guard or with patterns that can fail nested in it doesn't count.
+fn check_exhaustive(compiler: &mut Compiler, arms: &[MatchArm]) {
+    let mut enum_name = None;
+    let mut covered = HashSet::new();
+    for arm in arms {
+        match arm.pattern() {
+            pattern if pattern.is_irrefutable() && arm.guard().is_none() => return,
+            Pattern::Variant {
+                enum_,
+                variant,
+                fields,
+            } => {
+                if *enum_name.get_or_insert(enum_) != enum_ {
+                    return;
+                }
+                if arm.guard().is_none() && fields.iter().all(Pattern::is_irrefutable) {
+                    covered.insert(variant);
+                }
+            }
+            _ => return,
+        }
+    }
+
+    let enum_ = match enum_name {
+        Some(enum_) => enum_,
+        None => return,
+    };
+    let missing: Vec<String> = match compiler.enum_variants(enum_) {
+        Some(variants) => variants
+            .iter()
+            .filter(|variant| !covered.contains(variant))
+            .map(|variant| format!("{}.{}", enum_, variant))
+            .collect(),
+        None => return,
+    };
+
+    if !missing.is_empty() {
+        compiler.add_error(CompilerError::NonExhaustiveMatch(missing));
+    }
+}
+
+fn compile_block(compiler: &mut Compiler, block: BlockDecl) {
     compiler.begin_scope();
-    for expr in *block {
-        compile_expr(compiler, expr);
+    for expr in block {
+        compile_statement(compiler, expr);
     }
     compiler.end_scope();
 }
 
-fn compile_print(compiler: &mut Compiler, expr: Box<Expr>) {
-    compile_expr(compiler, *expr);
+fn compile_print(compiler: &mut Compiler, expr: Expr) {
+    compile_expr(compiler, expr);
     compiler.emit(Opcode::Print);
 }
 
@@ -221,6 +877,6 @@ fn compile_literal(compiler: &mut Compiler, literal: LiteralExpr) {
         LiteralExpr::String(s) => compiler.emit_string(&s),
         LiteralExpr::True => compiler.emit_constant(Value::Bool(true)),
         LiteralExpr::False => compiler.emit_constant(Value::Bool(false)),
-        LiteralExpr::Nil => todo!(), // TODO: Compile nil literals.
+        LiteralExpr::Nil => compiler.emit(Opcode::Nil),
     }
 }
@@ -1,13 +1,50 @@
 use crate::compiler::error::{CompileResult, CompilerError};
-use crate::compiler::local::Locals;
+use crate::compiler::local::{Locals, Upvalue};
 use crate::compiler::object::{Function, FunctionType};
 use crate::vm::opcode::StackIndex;
 
+/// A loop being compiled: where `continue` jumps back to, the scope depth
+/// outside its body and the `break` jumps still waiting for its end.
+#[derive(Clone)]
+pub struct Loop {
+    start: usize,
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+impl Loop {
+    pub fn new(start: usize, depth: usize) -> Self {
+        Loop {
+            start,
+            depth,
+            breaks: vec![],
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn add_break(&mut self, offset: usize) {
+        self.breaks.push(offset);
+    }
+
+    pub fn into_breaks(self) -> Vec<usize> {
+        self.breaks
+    }
+}
+
 #[derive(Clone)]
 pub struct CompilerInstance {
     function: Function,
     function_type: FunctionType,
     locals: Locals,
+    upvalues: Vec<Upvalue>,
+    loops: Vec<Loop>,
     enclosing: Box<Option<CompilerInstance>>,
 }
 
@@ -17,9 +54,18 @@ impl CompilerInstance {
             function: Function::new(),
             function_type,
             locals: Locals::new(),
+            upvalues: vec![],
+            loops: vec![],
             enclosing: Box::new(None),
         };
-        instance.locals_mut().insert(""); // TODO:
+        // The first slot holds the function being called, or the receiver
+        // of a method, which is what `self` refers to.
+        if instance.function_type == FunctionType::Method {
+            instance.locals_mut().insert("self", false);
+        } else {
+            instance.locals_mut().insert("", false);
+        }
+        instance.locals_mut().mark_initialized();
 
         instance
     }
@@ -36,6 +82,47 @@ impl CompilerInstance {
         Ok(None)
     }
 
+    /// Resolves a variable declared in one of the enclosing functions,
+    /// registering an upvalue in every function in between.
+    pub fn resolve_upvalue(&mut self, name: &str) -> CompileResult<Option<StackIndex>> {
+        let enclosing = match &mut *self.enclosing {
+            Some(enclosing) => enclosing,
+            None => return Ok(None),
+        };
+
+        if let Some(local) = enclosing.resolve_local(name)? {
+            enclosing.locals_mut().mark_captured(local);
+            return Ok(Some(self.add_upvalue(local, true)));
+        }
+
+        if let Some(upvalue) = enclosing.resolve_upvalue(name)? {
+            return Ok(Some(self.add_upvalue(upvalue, false)));
+        }
+
+        Ok(None)
+    }
+
+    fn add_upvalue(&mut self, index: StackIndex, is_local: bool) -> StackIndex {
+        let upvalue = Upvalue::new(index, is_local);
+        if let Some(existing) = self.upvalues.iter().position(|u| u == &upvalue) {
+            return existing;
+        }
+
+        self.upvalues.push(upvalue);
+        self.upvalues.len() - 1
+    }
+
+    /// Whether a local, or a local captured from an enclosing function, is
+    /// mutable. Returns `None` for globals.
+    pub fn local_mutability(&self, name: &str) -> Option<bool> {
+        match self.locals.get(name) {
+            Some(local) => Some(local.mutable()),
+            None => (*self.enclosing)
+                .as_ref()
+                .and_then(|enclosing| enclosing.local_mutability(name)),
+        }
+    }
+
     pub fn function(&self) -> &Function {
         &self.function
     }
@@ -56,7 +143,15 @@ impl CompilerInstance {
         &mut self.locals
     }
 
-    pub fn enclosing(&self) -> &Box<Option<CompilerInstance>> {
+    pub fn upvalues(&self) -> &[Upvalue] {
+        &self.upvalues
+    }
+
+    pub fn loops_mut(&mut self) -> &mut Vec<Loop> {
+        &mut self.loops
+    }
+
+    pub fn enclosing(&self) -> &Option<CompilerInstance> {
         &self.enclosing
     }
 
@@ -1,17 +1,23 @@
+use crate::vm::opcode::StackIndex;
+
 #[derive(Debug, Clone)]
 pub struct Local {
     name: String,
     depth: usize,
     initialized: bool,
+    mutable: bool,
+    captured: bool,
     slot: usize,
 }
 
 impl Local {
-    pub fn new(name: String, depth: usize, initialized: bool, slot: usize) -> Self {
+    pub fn new(name: String, depth: usize, initialized: bool, mutable: bool, slot: usize) -> Self {
         Local {
             name,
             depth,
             initialized,
+            mutable,
+            captured: false,
             slot,
         }
     }
@@ -23,6 +29,36 @@ impl Local {
     pub fn initialized(&self) -> bool {
         self.initialized
     }
+
+    pub fn mutable(&self) -> bool {
+        self.mutable
+    }
+
+    pub fn captured(&self) -> bool {
+        self.captured
+    }
+}
+
+/// A variable captured by a closure, either a local of the directly
+/// enclosing function or one of that function's own upvalues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upvalue {
+    index: StackIndex,
+    is_local: bool,
+}
+
+impl Upvalue {
+    pub fn new(index: StackIndex, is_local: bool) -> Self {
+        Upvalue { index, is_local }
+    }
+
+    pub fn index(&self) -> StackIndex {
+        self.index
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.is_local
+    }
 }
 
 #[derive(Clone)]
@@ -39,6 +75,11 @@ impl Locals {
         }
     }
 
+    /// The number of locals declared, including temporaries.
+    pub fn count(&self) -> usize {
+        self.stack.len()
+    }
+
     pub fn scope_depth(&self) -> usize {
         self.scope_depth
     }
@@ -65,20 +106,61 @@ impl Locals {
         self.stack.split_off(index)
     }
 
+    /// The locals declared deeper than `depth`, innermost first.
+    pub fn deeper_than(&self, depth: usize) -> impl Iterator<Item = &Local> {
+        self.stack.iter().rev().take_while(move |l| l.depth > depth)
+    }
+
     pub fn mark_initialized(&mut self) {
         let index = self.stack.len() - 1;
         self.stack[index].initialized = true;
     }
 
-    pub fn insert(&mut self, ident: &str) {
-        if self.get_at_depth(&ident, self.scope_depth).is_some() {
+    pub fn mark_captured(&mut self, slot: StackIndex) {
+        if let Some(local) = self.stack.iter_mut().find(|l| l.slot == slot) {
+            local.captured = true;
+        }
+    }
+
+    pub fn insert(&mut self, ident: &str, mutable: bool) {
+        if self.get_at_depth(ident, self.scope_depth).is_some() {
             return;
         }
 
-        let local = Local::new(ident.to_string(), self.scope_depth, false, self.stack.len());
+        let local = Local::new(
+            ident.to_string(),
+            self.scope_depth,
+            false,
+            mutable,
+            self.next_slot(),
+        );
+        self.stack.push(local);
+    }
+
+    /// Reserves the slot of a value that stays on the stack while the rest
+    /// of an expression is compiled.
+    pub fn push_temporary(&mut self) {
+        let slot = self.next_slot();
+        let local = Local::new(
+            "(temporary)".to_string(),
+            self.scope_depth,
+            true,
+            false,
+            slot,
+        );
         self.stack.push(local);
     }
 
+    pub fn pop_temporaries(&mut self, count: usize) {
+        self.stack.truncate(self.stack.len() - count);
+    }
+
+    /// The slot the next value pushed lands in. A local whose initializer is
+    /// still being compiled has no value on the stack yet.
+    fn next_slot(&self) -> usize {
+        self.stack.iter().filter(|l| l.initialized).count()
+    }
+
     pub fn get_at_depth(&self, ident: &str, depth: usize) -> Option<&Local> {
         self.stack
             .iter()
@@ -90,6 +172,7 @@ impl Locals {
     }
 
     pub fn get(&self, ident: &str) -> Option<&Local> {
-        self.stack.iter().find(|l| l.name == ident)
+        // Search from the innermost scope outwards so shadowing works.
+        self.stack.iter().rev().find(|l| l.name == ident)
     }
 }
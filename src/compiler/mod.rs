@@ -1,29 +1,109 @@
 use crate::compiler::compiler::Compiler;
-use crate::compiler::error::CompileResult;
-use crate::compiler::expr_compiler::compile_expr;
+use crate::compiler::expr_compiler::compile_module;
 use crate::compiler::object::Function;
+use crate::compiler::value::Value;
+use crate::diagnostic::{end_of_source, Diagnostic};
 use crate::lexer::lex;
+use crate::parser::ast::Identifier;
 use crate::parser::parse;
+use crate::typechecker::check;
+use std::collections::HashSet;
 
 pub mod chunk;
 pub mod compiler;
-mod error;
+pub mod error;
 mod expr_compiler;
 mod instance;
 mod local;
 pub mod object;
 pub mod value;
 
-pub fn compile(source: &str) -> CompileResult<Function> {
-    // TODO: Report errors.
-    let mut tokens = lex(source).unwrap();
-    let ast = parse(&mut tokens).unwrap();
+/// Compiles the source into the top-level script function, or returns the
+/// diagnostics of the first stage (lexing, parsing, type checking or
+/// compiling) that failed.
+pub fn compile(source: &str) -> Result<Function, Vec<Diagnostic>> {
+    compile_with(source, &mut Compiler::new(), false)
+}
+
+/// Compiles one REPL input. `immutable_globals` holds the globals earlier
+/// inputs bound with `val` and is updated once the input compiles. A trailing
+/// bare expression is returned from the script so its value can be echoed.
+pub fn compile_repl(
+    source: &str,
+    immutable_globals: &mut HashSet<Identifier>,
+) -> Result<Function, Vec<Diagnostic>> {
+    let mut compiler = Compiler::with_immutable_globals(immutable_globals.clone());
+    let fun = compile_with(source, &mut compiler, true)?;
+    *immutable_globals = compiler.immutable_globals().clone();
+
+    Ok(fun)
+}
+
+fn compile_with(
+    source: &str,
+    compiler: &mut Compiler,
+    echo: bool,
+) -> Result<Function, Vec<Diagnostic>> {
+    let mut tokens = lex(source).map_err(|errors| {
+        errors
+            .iter()
+            .map(|error| {
+                Diagnostic::error(error.to_string(), error.position(), source)
+                    .with_hint(error.hint())
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    let ast = parse(&mut tokens).map_err(|errors| {
+        errors
+            .iter()
+            .map(|error| {
+                let position = error
+                    .position()
+                    .copied()
+                    .unwrap_or_else(|| end_of_source(source));
+                Diagnostic::error(error.to_string(), &position, source).with_hint(error.hint())
+            })
+            .collect::<Vec<_>>()
+    })?;
 
-    let mut compiler = Compiler::new();
+    check(&ast).map_err(|errors| {
+        errors
+            .iter()
+            .map(|(error, position)| {
+                Diagnostic::error(error.to_string(), position, source).with_hint(error.hint())
+            })
+            .collect::<Vec<_>>()
+    })?;
+
+    compile_module(compiler, ast, echo);
+
+    let fun = compiler.end_compiler();
+
+    let diagnostics: Vec<Diagnostic> = compiler
+        .errors()
+        .iter()
+        .map(|(error, position)| {
+            Diagnostic::error(error.to_string(), position, source).with_hint(error.hint())
+        })
+        .collect();
+
+    if diagnostics.is_empty() {
+        Ok(fun)
+    } else {
+        Err(diagnostics)
+    }
+}
 
-    for expr in ast {
-        compile_expr(&mut compiler, expr);
+/// The disassembly of the function's chunk followed by that of every function
+/// declared in it.
+pub fn disassemble(fun: &Function) -> String {
+    let mut out = fun.chunk().to_string();
+    for constant in fun.chunk().constants() {
+        if let Value::Function(nested) = constant {
+            out += &disassemble(nested);
+        }
     }
 
-    Ok(compiler.end_compiler())
+    out
 }
@@ -1,5 +1,9 @@
 use crate::compiler::chunk::Chunk;
+use crate::compiler::value::{write_nested, HashKey, Value};
+use crate::vm::error::{RunResult, RuntimeError};
 use crate::vm::obj::Gc;
+use crate::vm::opcode::StackIndex;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Formatter;
 
@@ -7,17 +11,498 @@ use std::fmt::Formatter;
 pub enum FunctionType {
     Closure,
     Function,
+    Method,
     Script,
 }
 
 #[derive(Debug, Clone)]
 pub struct Closure {
     pub fun: Gc<Function>,
+    pub upvalues: Vec<Gc<Upvalue>>,
 }
 
 impl Closure {
-    pub fn new(fun: Gc<Function>) -> Closure {
-        Closure { fun }
+    pub fn new(fun: Gc<Function>, upvalues: Vec<Gc<Upvalue>>) -> Closure {
+        Closure { fun, upvalues }
+    }
+}
+
+/// A captured variable. While open it points at a slot on the VM stack,
+/// once the slot goes out of scope the value is moved into `closed`.
+#[derive(Debug, Clone)]
+pub struct Upvalue {
+    location: StackIndex,
+    closed: Option<Value>,
+}
+
+impl Upvalue {
+    pub fn new(location: StackIndex) -> Self {
+        Upvalue {
+            location,
+            closed: None,
+        }
+    }
+
+    pub fn location(&self) -> StackIndex {
+        self.location
+    }
+
+    pub fn closed(&self) -> Option<&Value> {
+        self.closed.as_ref()
+    }
+
+    pub fn close(&mut self, value: Value) {
+        self.closed = Some(value);
+    }
+}
+
+/// A growable list of values, shared by every value that refers to it.
+#[derive(Debug, Clone, Default)]
+pub struct List {
+    elements: Vec<Value>,
+}
+
+impl List {
+    pub fn new(elements: Vec<Value>) -> Self {
+        List { elements }
+    }
+
+    pub fn elements(&self) -> &[Value] {
+        &self.elements
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    pub fn push(&mut self, value: Value) {
+        self.elements.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<Value> {
+        self.elements.pop()
+    }
+
+    pub fn get(&self, index: &Value) -> RunResult<Value> {
+        let index = self.resolve_index(index)?;
+        Ok(self.elements[index].clone())
+    }
+
+    pub fn set(&mut self, index: &Value, value: Value) -> RunResult<()> {
+        let index = self.resolve_index(index)?;
+        self.elements[index] = value;
+        Ok(())
+    }
+
+    /// Turns an index into a position in the list, negative indices count
+    /// back from the end.
+    fn resolve_index(&self, index: &Value) -> RunResult<usize> {
+        let index = match index {
+            Value::Number(n) if n.fract() == 0.0 => *n as i64,
+            Value::Number(n) => return Err(RuntimeError::InvalidIndex(n.to_string())),
+            other => return Err(RuntimeError::InvalidIndex(other.type_name().to_string())),
+        };
+
+        let len = self.elements.len() as i64;
+        let resolved = if index < 0 { len + index } else { index };
+        if resolved < 0 || resolved >= len {
+            return Err(RuntimeError::IndexOutOfBounds(index, self.elements.len()));
+        }
+
+        Ok(resolved as usize)
+    }
+}
+
+/// A map from hashable values to values. Entries are kept in the order
+/// their keys were first inserted.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    indices: HashMap<HashKey, usize>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Map::default()
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Value) -> RunResult<Option<&Value>> {
+        let index = self.indices.get(&key.hash_key()?);
+        Ok(index.map(|&i| &self.entries[i].1))
+    }
+
+    pub fn contains(&self, key: &Value) -> RunResult<bool> {
+        Ok(self.indices.contains_key(&key.hash_key()?))
+    }
+
+    pub fn insert(&mut self, key: Value, value: Value) -> RunResult<()> {
+        match self.indices.get(&key.hash_key()?) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indices.insert(key.hash_key()?, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A field of a class, `mutable` fields can be assigned after construction.
+#[derive(Debug, Clone)]
+pub struct Field {
+    name: String,
+    mutable: bool,
+}
+
+impl Field {
+    pub fn new(name: String, mutable: bool) -> Self {
+        Field { name, mutable }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+/// A set of methods a class promises to have, each with its arity.
+#[derive(Debug, Clone)]
+pub struct Trait {
+    name: String,
+    methods: Vec<(String, u8)>,
+}
+
+impl Trait {
+    pub fn new(name: String, methods: Vec<(String, u8)>) -> Self {
+        Trait { name, methods }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn methods(&self) -> &[(String, u8)] {
+        &self.methods
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Class {
+    name: String,
+    fields: Vec<Field>,
+    methods: HashMap<String, Gc<Closure>>,
+    traits: Vec<Gc<Trait>>,
+}
+
+impl Class {
+    pub fn new(name: String, fields: Vec<Field>) -> Self {
+        Class {
+            name,
+            fields,
+            methods: HashMap::new(),
+            traits: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// The position of the field among the class' fields.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field.name == name)
+    }
+
+    pub fn method(&self, name: &str) -> Option<Gc<Closure>> {
+        self.methods.get(name).copied()
+    }
+
+    pub fn add_method(&mut self, name: String, method: Gc<Closure>) {
+        self.methods.insert(name, method);
+    }
+
+    /// Records that the class implements the trait, after checking it has
+    /// every method the trait requires.
+    pub fn implement(&mut self, trait_: Gc<Trait>) -> RunResult<()> {
+        for (name, arity) in trait_.methods() {
+            let method = self.method(name).ok_or_else(|| {
+                RuntimeError::MissingTraitMethod(
+                    self.name.clone(),
+                    name.clone(),
+                    trait_.name().to_string(),
+                )
+            })?;
+
+            if method.fun.arity() != arity {
+                return Err(RuntimeError::TraitMethodArity(
+                    format!("{}.{}", self.name, name),
+                    *method.fun.arity(),
+                    trait_.name().to_string(),
+                    *arity,
+                ));
+            }
+        }
+
+        if !self.implements(trait_) {
+            self.traits.push(trait_);
+        }
+        Ok(())
+    }
+
+    pub fn implements(&self, trait_: Gc<Trait>) -> bool {
+        self.traits.iter().any(|t| t.ptr_eq(&trait_))
+    }
+}
+
+/// An instance of a class, holding a value for each of the class' fields
+/// in the order they were declared.
+#[derive(Debug, Clone)]
+pub struct Instance {
+    class: Gc<Class>,
+    values: Vec<Value>,
+}
+
+impl Instance {
+    pub fn new(class: Gc<Class>, values: Vec<Value>) -> Self {
+        Instance { class, values }
+    }
+
+    pub fn class(&self) -> Gc<Class> {
+        self.class
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.class
+            .field_index(name)
+            .map(|index| &self.values[index])
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) -> RunResult<()> {
+        let index = match self.class.field_index(name) {
+            Some(index) => index,
+            None => {
+                return Err(RuntimeError::UndefinedProperty(
+                    name.to_string(),
+                    self.class.name.clone(),
+                ))
+            }
+        };
+
+        if !self.class.fields[index].mutable {
+            return Err(RuntimeError::AssignToImmutableField(name.to_string()));
+        }
+        self.values[index] = value;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let placeholder = format!("{}(...)", self.class.name);
+        write_nested(f, self, &placeholder, |f| {
+            write!(f, "{}(", self.class.name)?;
+            for (i, (field, value)) in self.class.fields.iter().zip(&self.values).enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: {}", field.name, value.repr())?;
+            }
+            write!(f, ")")
+        })
+    }
+}
+
+/// A variant of an enum and the names of the values it carries.
+#[derive(Debug, Clone)]
+pub struct VariantDef {
+    name: String,
+    fields: Vec<String>,
+}
+
+impl VariantDef {
+    pub fn new(name: String, fields: Vec<String>) -> Self {
+        VariantDef { name, fields }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Enum {
+    name: String,
+    variants: Vec<VariantDef>,
+}
+
+impl Enum {
+    pub fn new(name: String, variants: Vec<VariantDef>) -> Self {
+        Enum { name, variants }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn variants(&self) -> &[VariantDef] {
+        &self.variants
+    }
+
+    /// The tag of the variant, its position among the enum's variants.
+    pub fn tag(&self, name: &str) -> Option<usize> {
+        self.variants
+            .iter()
+            .position(|variant| variant.name == name)
+    }
+}
+
+/// A value of an enum: which variant it is and the values it carries.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    enum_: Gc<Enum>,
+    tag: usize,
+    values: Vec<Value>,
+}
+
+impl Variant {
+    pub fn new(enum_: Gc<Enum>, tag: usize, values: Vec<Value>) -> Self {
+        Variant { enum_, tag, values }
+    }
+
+    pub fn enum_(&self) -> Gc<Enum> {
+        self.enum_
+    }
+
+    pub fn tag(&self) -> usize {
+        self.tag
+    }
+
+    pub fn name(&self) -> &str {
+        &self.enum_.variants[self.tag].name
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// The carried value named `name`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.enum_.variants[self.tag]
+            .fields
+            .iter()
+            .position(|field| field == name)
+            .map(|index| &self.values[index])
+    }
+}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Self) -> bool {
+        self.enum_.ptr_eq(&other.enum_) && self.tag == other.tag && self.values == other.values
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        if self.values.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "(")?;
+        for (i, value) in self.values.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value.repr())?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A method together with the value it was accessed on, which becomes
+/// `self` when the method is called.
+#[derive(Debug, Clone)]
+pub struct BoundMethod {
+    receiver: Value,
+    method: Gc<Closure>,
+}
+
+impl BoundMethod {
+    pub fn new(receiver: Value, method: Gc<Closure>) -> Self {
+        BoundMethod { receiver, method }
+    }
+
+    pub fn receiver(&self) -> &Value {
+        &self.receiver
+    }
+
+    pub fn method(&self) -> Gc<Closure> {
+        self.method
+    }
+}
+
+pub type NativeFn = fn(&[Value]) -> RunResult<Value>;
+
+/// A function implemented in Rust, like `len`.
+#[derive(Debug, Clone, Copy)]
+pub struct Native {
+    name: &'static str,
+    arity: u8,
+    fun: NativeFn,
+}
+
+impl Native {
+    pub const fn new(name: &'static str, arity: u8, fun: NativeFn) -> Self {
+        Native { name, arity, fun }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn arity(&self) -> u8 {
+        self.arity
+    }
+
+    pub fn call(&self, args: &[Value]) -> RunResult<Value> {
+        (self.fun)(args)
+    }
+}
+
+impl fmt::Display for Native {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
     }
 }
 
@@ -26,6 +511,7 @@ pub struct Function {
     name: String,
     chunk: Chunk,
     arity: u8,
+    upvalue_count: usize,
 }
 
 impl Function {
@@ -34,17 +520,31 @@ impl Function {
             name: String::new(),
             chunk: Chunk::new(),
             arity: 0,
+            upvalue_count: 0,
         }
     }
 
     pub fn set_name(&mut self, name: String) {
+        *self.chunk.name_mut() = Some(name.clone());
         self.name = name;
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn set_arity(&mut self, arity: u8) {
         self.arity = arity;
     }
 
+    pub fn set_upvalue_count(&mut self, upvalue_count: usize) {
+        self.upvalue_count = upvalue_count;
+    }
+
+    pub fn upvalue_count(&self) -> usize {
+        self.upvalue_count
+    }
+
     pub fn chunk(&self) -> &Chunk {
         &self.chunk
     }
@@ -62,6 +562,12 @@ impl Function {
     }
 }
 
+impl Default for Function {
+    fn default() -> Self {
+        Function::new()
+    }
+}
+
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         // TODO
@@ -1,5 +1,9 @@
-use crate::compiler::object::{Closure, Function};
+use crate::compiler::object::{
+    BoundMethod, Class, Closure, Enum, Function, Instance, List, Map, Native, Trait, Variant,
+};
+use crate::vm::error::{RunResult, RuntimeError};
 use crate::vm::obj::Gc;
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::fmt;
 use std::fmt::Formatter;
@@ -13,6 +17,28 @@ pub enum Value {
     String(String),
     Closure(Gc<Closure>),
     Function(Gc<Function>),
+    Native(Native),
+    Range(f64, f64),
+    List(Gc<List>),
+    Map(Gc<Map>),
+    Class(Gc<Class>),
+    Instance(Gc<Instance>),
+    BoundMethod(Gc<BoundMethod>),
+    Trait(Gc<Trait>),
+    Enum(Gc<Enum>),
+    Variant(Gc<Variant>),
+    /// A variant that carries values, called with them to create the value.
+    Constructor(Gc<Enum>, usize),
+}
+
+/// The part of a value a map hashes its keys by. Numbers are kept as their
+/// bits, with `-0` folded into `0` so keys that compare equal hash equally.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Number(u64),
+    Bool(bool),
+    Nil,
+    String(String),
 }
 
 impl fmt::Display for Value {
@@ -22,79 +48,157 @@ impl fmt::Display for Value {
             Value::String(s) => write!(f, "{}", s),
             Value::Bool(bool) => write!(f, "{}", bool),
             Value::Nil => write!(f, "nil"),
-            Value::Closure(clos) => write!(f, "Closure({:?})", clos),
+            Value::Closure(clos) => write!(f, "{}", *clos.fun),
             Value::Function(fun) => write!(f, "Function({})", **fun),
+            Value::Native(native) => write!(f, "{}", native),
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+            Value::List(list) => write_nested(f, &**list, "[...]", |f| {
+                write!(f, "[")?;
+                for (i, element) in list.elements().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, element)?;
+                }
+                write!(f, "]")
+            }),
+            Value::Map(map) => write_nested(f, &**map, "{...}", |f| {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.entries().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, key)?;
+                    write!(f, ": ")?;
+                    write_element(f, value)?;
+                }
+                write!(f, "}}")
+            }),
+            Value::Class(class) => write!(f, "<class {}>", class.name()),
+            Value::Trait(trait_) => write!(f, "<trait {}>", trait_.name()),
+            Value::Enum(enum_) => write!(f, "<enum {}>", enum_.name()),
+            Value::Variant(variant) => write!(f, "{}", **variant),
+            Value::Constructor(enum_, tag) => write!(
+                f,
+                "<variant {}.{}>",
+                enum_.name(),
+                enum_.variants()[*tag].name()
+            ),
+            Value::Instance(instance) => write!(f, "{}", **instance),
+            Value::BoundMethod(bound) => write!(f, "{}", *bound.method().fun),
         }
     }
 }
 
+/// Writes a value nested in a collection.
+fn write_element(f: &mut Formatter<'_>, value: &Value) -> fmt::Result {
+    write!(f, "{}", value.repr())
+}
+
+thread_local! {
+    /// The objects being printed further up the stack.
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    /// The pairs of objects being compared further up the stack.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Writes an object that can contain itself, the placeholder is written in
+/// place of the object when it turns up again inside itself.
+pub fn write_nested<T>(
+    f: &mut Formatter<'_>,
+    object: &T,
+    placeholder: &str,
+    write: impl FnOnce(&mut Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    let address = object as *const T as usize;
+    if PRINTING.with(|printing| printing.borrow().contains(&address)) {
+        return write!(f, "{}", placeholder);
+    }
+
+    PRINTING.with(|printing| printing.borrow_mut().push(address));
+    let result = write(f);
+    PRINTING.with(|printing| printing.borrow_mut().pop());
+    result
+}
+
+/// Compares two objects that can contain themselves. A pair that turns up
+/// again while it is being compared is taken to be equal, any difference is
+/// found where the comparison of the pair is still going on.
+fn eq_nested<T>(b: &T, a: &T, eq: impl FnOnce() -> bool) -> bool {
+    if std::ptr::eq(b, a) {
+        return true;
+    }
+
+    let pair = (b as *const T as usize, a as *const T as usize);
+    if COMPARING.with(|comparing| comparing.borrow().contains(&pair)) {
+        return true;
+    }
+
+    COMPARING.with(|comparing| comparing.borrow_mut().push(pair));
+    let result = eq();
+    COMPARING.with(|comparing| comparing.borrow_mut().pop());
+    result
+}
+
 impl From<&Value> for bool {
     fn from(value: &Value) -> Self {
-        match value {
-            Value::Bool(false) | Value::Nil => false,
-            _ => true,
-        }
+        !matches!(value, Value::Bool(false) | Value::Nil)
     }
 }
 
-impl Into<Value> for bool {
-    fn into(self) -> Value {
-        Value::Bool(self)
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
     }
 }
 
-// TODO: Return errors, not panic.
 impl Add for Value {
-    type Output = Self;
+    type Output = RunResult<Value>;
 
     fn add(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(b), Value::Number(a)) => Value::Number(b + a),
-            _ => panic!("Operand must be a number."),
+        if let (Value::String(b), Value::String(a)) = (&self, &other) {
+            return Ok(Value::String(format!("{}{}", b, a)));
         }
+
+        let (b, a) = numeric_operands("+", &self, &other)?;
+        Ok(Value::Number(b + a))
     }
 }
 
 impl Sub for Value {
-    type Output = Self;
+    type Output = RunResult<Value>;
 
     fn sub(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(b), Value::Number(a)) => Value::Number(b - a),
-            _ => panic!("Operand must be a number."),
-        }
+        let (b, a) = numeric_operands("-", &self, &other)?;
+        Ok(Value::Number(b - a))
     }
 }
 
 impl Mul for Value {
-    type Output = Self;
+    type Output = RunResult<Value>;
 
     fn mul(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(b), Value::Number(a)) => Value::Number(b * a),
-            _ => panic!("Operand must be a number."),
-        }
+        let (b, a) = numeric_operands("*", &self, &other)?;
+        Ok(Value::Number(b * a))
     }
 }
 
 impl Div for Value {
-    type Output = Self;
+    type Output = RunResult<Value>;
 
     fn div(self, other: Self) -> Self::Output {
-        match (self, other) {
-            (Value::Number(b), Value::Number(a)) => Value::Number(b / a),
-            _ => panic!("Operand must be a number."),
-        }
+        let (b, a) = numeric_operands("/", &self, &other)?;
+        Ok(Value::Number(b / a))
     }
 }
 
 impl Neg for Value {
-    type Output = Self;
+    type Output = RunResult<Value>;
 
     fn neg(self) -> Self::Output {
         match self {
-            Value::Number(a) => Value::Number(-a),
-            _ => panic!("Operand must be a number."),
+            Value::Number(a) => Ok(Value::Number(-a)),
+            _ => Err(RuntimeError::InvalidOperand("-", self.type_name())),
         }
     }
 }
@@ -107,20 +211,187 @@ impl PartialEq for Value {
         match (b, a) {
             (Value::Number(b), Value::Number(a)) => b == a,
             (Value::Bool(b), Value::Bool(a)) => b == a,
+            (Value::String(b), Value::String(a)) => b == a,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Range(b_start, b_end), Value::Range(a_start, a_end)) => {
+                b_start == a_start && b_end == a_end
+            }
+            (Value::Closure(b), Value::Closure(a)) => b.ptr_eq(a),
+            (Value::Native(b), Value::Native(a)) => b.name() == a.name(),
+            (Value::List(b), Value::List(a)) => {
+                eq_nested(&**b, &**a, || b.elements() == a.elements())
+            }
+            (Value::Map(b), Value::Map(a)) => eq_nested(&**b, &**a, || {
+                b.len() == a.len()
+                    && b.entries()
+                        .iter()
+                        .all(|(key, value)| matches!(a.get(key), Ok(Some(other)) if value == other))
+            }),
+            (Value::Class(b), Value::Class(a)) => b.ptr_eq(a),
+            (Value::Trait(b), Value::Trait(a)) => b.ptr_eq(a),
+            (Value::Enum(b), Value::Enum(a)) => b.ptr_eq(a),
+            (Value::Variant(b), Value::Variant(a)) => **b == **a,
+            (Value::Constructor(b, b_tag), Value::Constructor(a, a_tag)) => {
+                b.ptr_eq(a) && b_tag == a_tag
+            }
+            (Value::Instance(b), Value::Instance(a)) => b.ptr_eq(a),
+            (Value::BoundMethod(b), Value::BoundMethod(a)) => {
+                b.receiver() == a.receiver() && b.method().ptr_eq(&a.method())
+            }
             _ => false,
         }
     }
 }
 
-impl PartialOrd for Value {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let b = self;
-        let a = other;
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::String(_) => "string",
+            Value::Closure(_)
+            | Value::Function(_)
+            | Value::Native(_)
+            | Value::BoundMethod(_)
+            | Value::Constructor(..) => "function",
+            Value::Range(..) => "range",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Class(_) => "class",
+            Value::Trait(_) => "trait",
+            Value::Enum(_) => "enum",
+            Value::Variant(_) => "variant",
+            Value::Instance(_) => "instance",
+        }
+    }
 
-        match (b, a) {
-            (Value::Number(b), Value::Number(a)) => b.partial_cmp(a),
-            (Value::Bool(b), Value::Bool(a)) => b.partial_cmp(a),
-            _ => None,
+    /// The value as it is shown inside a collection or an error message,
+    /// strings are quoted so they can be told apart from other values.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::String(s) => format!("{:?}", s),
+            _ => self.to_string(),
         }
     }
+
+    /// The key a map stores the value under, only strings, numbers, bools
+    /// and nil can be keys.
+    pub fn hash_key(&self) -> RunResult<HashKey> {
+        match self {
+            Value::Number(n) if n.is_nan() => Err(RuntimeError::Unhashable("NaN")),
+            Value::Number(n) if *n == 0.0 => Ok(HashKey::Number(0f64.to_bits())),
+            Value::Number(n) => Ok(HashKey::Number(n.to_bits())),
+            Value::Bool(b) => Ok(HashKey::Bool(*b)),
+            Value::Nil => Ok(HashKey::Nil),
+            Value::String(s) => Ok(HashKey::String(s.clone())),
+            _ => Err(RuntimeError::Unhashable(self.type_name())),
+        }
+    }
+
+    /// The range from this number up to, but not including, `end`.
+    pub fn range(&self, end: &Value) -> RunResult<Value> {
+        let (start, end) = numeric_operands("..", self, end)?;
+        Ok(Value::Range(start, end))
+    }
+
+    /// The element at `index` when iterating over the value, `None` once the
+    /// iteration is done.
+    pub fn iteration_element(&self, index: usize) -> RunResult<Option<Value>> {
+        match self {
+            Value::Range(start, end) => {
+                let n = start + index as f64;
+                Ok(if n < *end {
+                    Some(Value::Number(n))
+                } else {
+                    None
+                })
+            }
+            Value::List(list) => Ok(list.elements().get(index).cloned()),
+            Value::Map(map) => Ok(map.entries().get(index).map(|(key, _)| key.clone())),
+            _ => Err(RuntimeError::NotIterable(self.type_name())),
+        }
+    }
+
+    /// The element at `index` of a list, or the value stored under the key
+    /// `index` of a map.
+    pub fn index(&self, index: &Value) -> RunResult<Value> {
+        match self {
+            Value::List(list) => list.get(index),
+            Value::Map(map) => match map.get(index)? {
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::MissingKey(index.repr())),
+            },
+            _ => Err(RuntimeError::NotIndexable(self.type_name())),
+        }
+    }
+
+    /// Replaces the element at `index` of a list, or stores the value under
+    /// the key `index` of a map.
+    pub fn set_index(&self, index: &Value, value: Value) -> RunResult<()> {
+        match self {
+            Value::List(list) => {
+                let mut list = *list;
+                list.set(index, value)
+            }
+            Value::Map(map) => {
+                let mut map = *map;
+                map.insert(index.clone(), value)
+            }
+            _ => Err(RuntimeError::NotIndexable(self.type_name())),
+        }
+    }
+
+    pub fn greater(&self, other: &Value) -> RunResult<bool> {
+        let ordering = compare(">", self, other)?;
+        Ok(ordering == Some(Ordering::Greater))
+    }
+
+    pub fn greater_equal(&self, other: &Value) -> RunResult<bool> {
+        let ordering = compare(">=", self, other)?;
+        Ok(matches!(
+            ordering,
+            Some(Ordering::Greater) | Some(Ordering::Equal)
+        ))
+    }
+
+    pub fn less(&self, other: &Value) -> RunResult<bool> {
+        let ordering = compare("<", self, other)?;
+        Ok(ordering == Some(Ordering::Less))
+    }
+
+    pub fn less_equal(&self, other: &Value) -> RunResult<bool> {
+        let ordering = compare("<=", self, other)?;
+        Ok(matches!(
+            ordering,
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        ))
+    }
+}
+
+/// Orders two numbers, or two strings lexicographically. `None` means the
+/// numbers are unordered (NaN).
+fn compare(op: &'static str, b: &Value, a: &Value) -> RunResult<Option<Ordering>> {
+    match (b, a) {
+        (Value::Number(b), Value::Number(a)) => Ok(b.partial_cmp(a)),
+        (Value::String(b), Value::String(a)) => Ok(Some(b.cmp(a))),
+        _ => Err(RuntimeError::InvalidOperands(
+            op,
+            b.type_name(),
+            a.type_name(),
+        )),
+    }
+}
+
+/// Unwraps the operands of a numeric operator, `op` is only used to
+/// describe the operator when the operands are not both numbers.
+fn numeric_operands(op: &'static str, b: &Value, a: &Value) -> RunResult<(f64, f64)> {
+    match (b, a) {
+        (Value::Number(b), Value::Number(a)) => Ok((*b, *a)),
+        _ => Err(RuntimeError::InvalidOperands(
+            op,
+            b.type_name(),
+            a.type_name(),
+        )),
+    }
 }
@@ -0,0 +1,164 @@
+use crate::lexer::token::Position;
+use std::fmt;
+use std::fmt::Formatter;
+
+pub mod render;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "Error"),
+            Severity::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+/// A problem found in a source file by the lexer, parser or compiler.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    severity: Severity,
+    message: String,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    hint: Option<String>,
+    notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String, position: &Position, source: &str) -> Self {
+        Diagnostic {
+            severity,
+            message,
+            start: *position.start(),
+            end: *position.end(),
+            line: *position.line(),
+            column: column(source, *position.start()),
+            hint: None,
+            notes: vec![],
+        }
+    }
+
+    /// A diagnostic for a whole line, for errors that only know their line.
+    pub fn at_line(severity: Severity, message: String, line: usize, source: &str) -> Self {
+        Diagnostic::new(severity, message, &line_position(source, line), source)
+    }
+
+    /// Adds a suggestion on how to fix the problem.
+    pub fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+
+    /// Adds extra context, shown after the snippet.
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn error(message: String, position: &Position, source: &str) -> Self {
+        Diagnostic::new(Severity::Error, message, position, source)
+    }
+
+    pub fn warning(message: String, position: &Position, source: &str) -> Self {
+        Diagnostic::new(Severity::Warning, message, position, source)
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The byte range of the offending source.
+    pub fn span(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] {}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+/// The 1-based column, in characters, of the byte offset `start`.
+fn column(source: &str, start: usize) -> usize {
+    let start = start.min(source.len());
+    let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    source[line_start..start].chars().count() + 1
+}
+
+/// The position of the text on a 1-based line, without its indentation.
+fn line_position(source: &str, line: usize) -> Position {
+    let mut start = 0;
+    for (i, text) in source.split('\n').enumerate() {
+        if i + 1 == line {
+            let indent = text.len() - text.trim_start().len();
+            let end = start + text.trim_end().len();
+            return Position::new((start + indent).min(end), end, line);
+        }
+        start += text.len() + 1;
+    }
+
+    end_of_source(source)
+}
+
+/// The position just past the last character of `source`.
+pub fn end_of_source(source: &str) -> Position {
+    let line = source.matches('\n').count() + 1;
+    Position::new(source.len(), source.len(), line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_column() {
+        let source = "val a = 1\nval b = §c";
+        let position = Position::new(20, 21, 2);
+
+        let diagnostic =
+            Diagnostic::error("Unexpected character 'c'.".to_string(), &position, source);
+        assert_eq!(2, diagnostic.line());
+        assert_eq!(10, diagnostic.column());
+        assert_eq!(
+            "[line 2:10] Error: Unexpected character 'c'.",
+            diagnostic.to_string()
+        );
+    }
+}
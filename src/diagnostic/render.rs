@@ -0,0 +1,168 @@
+use crate::diagnostic::{Diagnostic, Severity};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics like rustc does: the message, the offending source
+/// line with its span underlined, and any hint or notes.
+pub struct Renderer<'a> {
+    source: &'a str,
+    name: &'a str,
+    color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    /// A plain renderer for `source`, `name` is shown as its file name.
+    pub fn new(source: &'a str, name: &'a str) -> Self {
+        Renderer {
+            source,
+            name,
+            color: false,
+        }
+    }
+
+    /// Enables or disables ANSI colours.
+    pub fn colored(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let severity = match diagnostic.severity() {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+
+        let line = diagnostic.line().to_string();
+        let gutter = " ".repeat(line.len());
+        let (text, marker) = self.snippet(diagnostic);
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(&format!("{}:", severity.0), severity.1),
+            self.paint(&format!(" {}", diagnostic.message()), BOLD)
+        );
+        out += &format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            self.paint("-->", BLUE),
+            self.name,
+            diagnostic.line(),
+            diagnostic.column()
+        );
+        out += &format!("{} {}\n", gutter, self.paint("|", BLUE));
+        out += &format!("{} {}\n", self.paint(&format!("{} |", line), BLUE), text);
+        out += &format!(
+            "{} {} {}",
+            gutter,
+            self.paint("|", BLUE),
+            self.paint(&marker, severity.1)
+        );
+
+        if let Some(hint) = diagnostic.hint() {
+            out += &format!("\n{} {} {}", gutter, self.paint("= help:", BOLD), hint);
+        }
+        for note in diagnostic.notes() {
+            out += &format!("\n{} {} {}", gutter, self.paint("= note:", BOLD), note);
+        }
+
+        out
+    }
+
+    /// The source line containing the diagnostic and the carets that
+    /// underline its span, a span running past the line is cut off at its end.
+    fn snippet(&self, diagnostic: &Diagnostic) -> (&'a str, String) {
+        let (start, end) = diagnostic.span();
+        let start = start.min(self.source.len());
+
+        let line_start = self.source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = self.source[start..]
+            .find('\n')
+            .map_or(self.source.len(), |i| start + i);
+        let text = self.source[line_start..line_end].trim_end_matches('\r');
+
+        // Tabs are kept so the carets line up with the source.
+        let indent: String = self.source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.source[start..end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        (text, format!("{}{}", indent, "^".repeat(width)))
+    }
+
+    fn paint(&self, text: &str, style: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::token::Position;
+
+    #[test]
+    fn render_plain() {
+        let source = "val a = 1\n\ta = 2\n";
+        let diagnostic = Diagnostic::error(
+            "Cannot assign to immutable variable 'a'.".to_string(),
+            &Position::new(11, 16, 2),
+            source,
+        )
+        .with_hint(Some(
+            "declare 'a' with 'var' to make it mutable".to_string(),
+        ));
+
+        assert_eq!(
+            "error: Cannot assign to immutable variable 'a'.\n \
+             --> test.un:2:2\n  \
+             |\n\
+             2 | \ta = 2\n  \
+             | \t^^^^^\n  \
+             = help: declare 'a' with 'var' to make it mutable",
+            Renderer::new(source, "test.un").render(&diagnostic)
+        );
+    }
+
+    #[test]
+    fn render_end_of_file() {
+        let source = "print(1";
+        let diagnostic = Diagnostic::error(
+            "Expected ')' but found end of file.".to_string(),
+            &Position::new(7, 7, 1),
+            source,
+        );
+
+        assert_eq!(
+            "error: Expected ')' but found end of file.\n \
+             --> test.un:1:8\n  \
+             |\n\
+             1 | print(1\n  \
+             |        ^",
+            Renderer::new(source, "test.un").render(&diagnostic)
+        );
+    }
+
+    #[test]
+    fn render_colored() {
+        let source = "x";
+        let diagnostic =
+            Diagnostic::warning("Unused.".to_string(), &Position::new(0, 1, 1), source);
+
+        let rendered = Renderer::new(source, "test.un")
+            .colored(true)
+            .render(&diagnostic);
+        assert!(rendered.starts_with("\x1b[1;33mwarning:\x1b[0m"));
+        assert!(rendered.ends_with("\x1b[1;33m^\x1b[0m"));
+    }
+}
@@ -1,7 +1,42 @@
+use crate::lexer::token::Position;
+use std::fmt;
+use std::fmt::Formatter;
+
 pub type LexResult<T> = std::result::Result<T, SyntaxError>;
 
 #[derive(Debug, Clone)]
 pub enum SyntaxError {
-    UnterminatedString,
-    UnexpectedEOF,
+    UnterminatedString(Position),
+    UnexpectedCharacter(char, Position),
+    UnexpectedEOF(Position),
+}
+
+impl SyntaxError {
+    pub fn position(&self) -> &Position {
+        match self {
+            SyntaxError::UnterminatedString(position)
+            | SyntaxError::UnexpectedCharacter(_, position)
+            | SyntaxError::UnexpectedEOF(position) => position,
+        }
+    }
+
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            SyntaxError::UnterminatedString(_) => {
+                Some("add a closing '\"' to end the string".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxError::UnterminatedString(_) => write!(f, "Unterminated string."),
+            SyntaxError::UnexpectedCharacter(c, _) => write!(f, "Unexpected character '{}'.", c),
+            SyntaxError::UnexpectedEOF(_) => write!(f, "Unexpected end of file."),
+        }
+    }
 }
@@ -3,10 +3,23 @@ use crate::lexer::token::*;
 use std::iter::Peekable;
 use std::str::CharIndices;
 
+/// An interpolation in a string the lexer is in the middle of.
+enum Interpolation {
+    /// `$name`, the string continues right after the name.
+    Name,
+    /// `${expr}`, the string continues after the `}` closing the expression.
+    /// `depth` counts the braces opened inside the expression, `position`
+    /// is that of the `${`.
+    Expr { depth: usize, position: Position },
+}
+
 pub struct Lexer<'a> {
     source: &'a str,
     chars: Peekable<CharIndices<'a>>,
     line: usize,
+    interpolations: Vec<Interpolation>,
+    /// Whether the string continues after the name just lexed.
+    resume_string: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -15,21 +28,37 @@ impl<'a> Lexer<'a> {
             source,
             chars: source.char_indices().peekable(),
             line: 1,
+            interpolations: vec![],
+            resume_string: false,
         }
     }
 
     pub fn read_token(&mut self) -> LexResult<Option<Token<'a>>> {
+        if self.resume_string {
+            self.resume_string = false;
+            let start = self.offset();
+            return self.string_part(start, true);
+        }
+        if let Some(Interpolation::Name) = self.interpolations.last() {
+            self.interpolations.pop();
+            self.resume_string = true;
+            let start = self.offset();
+            return self.identifier(start);
+        }
+
         self.skip_whitespace();
         if self.is_at_end() {
             return self.eof();
         }
 
-        let (start, c) = self.advance().ok_or(SyntaxError::UnexpectedEOF)?;
+        let (start, c) = self
+            .advance()
+            .ok_or_else(|| SyntaxError::UnexpectedEOF(self.end_position()))?;
 
         if c.is_alphabetic() {
             return self.identifier(start);
         }
-        if c.is_digit(10) {
+        if c.is_ascii_digit() {
             return self.number(start);
         }
 
@@ -38,13 +67,37 @@ impl<'a> Lexer<'a> {
             ')' => TokenType::RightParen,
             '[' => TokenType::LeftBracket,
             ']' => TokenType::RightBracket,
-            '{' => TokenType::LeftBrace,
-            '}' => TokenType::RightBrace,
+            '{' => {
+                if let Some(Interpolation::Expr { depth, .. }) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                TokenType::LeftBrace
+            }
+            '}' => match self.interpolations.last_mut() {
+                // The end of an interpolated expression, the string continues.
+                Some(Interpolation::Expr { depth: 0, .. }) => {
+                    self.interpolations.pop();
+                    let start = self.offset();
+                    return self.string_part(start, true);
+                }
+                Some(Interpolation::Expr { depth, .. }) => {
+                    *depth -= 1;
+                    TokenType::RightBrace
+                }
+                _ => TokenType::RightBrace,
+            },
             ',' => TokenType::Comma,
-            '.' => TokenType::Dot,
+            '.' => {
+                if self.match_('.')? {
+                    TokenType::DotDot
+                } else {
+                    TokenType::Dot
+                }
+            }
             '+' => TokenType::Plus,
             '-' => TokenType::Minus,
             '*' => TokenType::Star,
+            '_' => TokenType::Underscore,
             '/' => {
                 // Ignore comments.
                 if self.check('/')? {
@@ -78,13 +131,21 @@ impl<'a> Lexer<'a> {
             '=' => {
                 if self.match_('=')? {
                     TokenType::EqualEqual
+                } else if self.match_('>')? {
+                    TokenType::FatArrow
                 } else {
                     TokenType::Equal
                 }
             }
+            '&' if self.match_('&')? => TokenType::And,
+            '|' if self.match_('|')? => TokenType::Or,
             ';' => TokenType::Semicolon,
+            ':' => TokenType::Colon,
             '"' => return self.string(start),
-            _ => todo!(), // TODO: ???
+            _ => {
+                let position = Position::new(start, start + c.len_utf8(), self.line);
+                return Err(SyntaxError::UnexpectedCharacter(c, position));
+            }
         };
 
         Ok(Some(self.make_token(token_type, start)))
@@ -101,17 +162,17 @@ impl<'a> Lexer<'a> {
     }
 
     fn number(&mut self, start: usize) -> LexResult<Option<Token<'a>>> {
-        self.advance_while(|c| c.is_digit(10));
+        self.advance_while(|c| c.is_ascii_digit());
 
         // Look for a fractional part
         if let Some(peek) = self.peek() {
             if peek == '.' {
                 if let Some(next) = self.peek_next() {
-                    if next.is_digit(10) {
+                    if next.is_ascii_digit() {
                         // Consume the '.'.
                         self.advance();
 
-                        self.advance_while(|c| c.is_digit(10));
+                        self.advance_while(|c| c.is_ascii_digit());
                     }
                 }
             }
@@ -121,27 +182,79 @@ impl<'a> Lexer<'a> {
     }
 
     fn string(&mut self, start: usize) -> LexResult<Option<Token<'a>>> {
-        let start = start + 1;
-        self.advance_while(|&c| c != '"');
-        if self.is_at_end() {
-            return Err(SyntaxError::UnterminatedString);
+        self.string_part(start + 1, false)
+    }
+
+    /// Lexes the string from `start` up to the closing '"', or up to the
+    /// next `$name` or `${expr}` in it. `\$` is a '$' that doesn't start an
+    /// interpolation. `resumed` is set for the part after an interpolation.
+    fn string_part(&mut self, start: usize, resumed: bool) -> LexResult<Option<Token<'a>>> {
+        let line = self.line;
+        let (part, end) = if resumed {
+            (TokenType::InterpolationMiddle, TokenType::InterpolationEnd)
+        } else {
+            (TokenType::Interpolation, TokenType::String)
+        };
+        loop {
+            match (self.peek(), self.peek_next()) {
+                (None, _) => {
+                    // Any interpolation the string is in is never closed
+                    // either, the error covers both.
+                    self.interpolations.clear();
+                    let position = Position::new(start - 1, self.source.len(), line);
+                    return Err(SyntaxError::UnterminatedString(position));
+                }
+                (Some('"'), _) => break,
+                (Some('\\'), Some('$')) => {
+                    self.advance();
+                    self.advance();
+                }
+                (Some('$'), Some('{')) => {
+                    let token = self.string_token(part.clone(), start);
+                    let offset = self.offset();
+                    let position = Position::new(offset, offset + 2, self.line);
+                    self.advance();
+                    self.advance();
+                    self.interpolations
+                        .push(Interpolation::Expr { depth: 0, position });
+                    return Ok(Some(token));
+                }
+                (Some('$'), Some(next)) if next.is_alphabetic() => {
+                    let token = self.string_token(part.clone(), start);
+                    self.advance();
+                    self.interpolations.push(Interpolation::Name);
+                    return Ok(Some(token));
+                }
+                _ => {
+                    self.advance();
+                }
+            }
         }
 
+        let token = self.string_token(end, start);
+
         // Consume the '"'.
         self.advance();
 
-        let source = self.token_contents(start);
-        let s = &source[0..source.len() - 1];
-        let token = Token::new(
-            TokenType::String,
-            s,
-            Position::new(start, start + s.len(), self.line),
-        );
-
         Ok(Some(token))
     }
 
+    fn string_token(&mut self, token_type: TokenType, start: usize) -> Token<'a> {
+        let end = self.offset();
+        Token::new(
+            token_type,
+            &self.source[start..end],
+            Position::new(start, end, self.line),
+        )
+    }
+
     fn eof(&mut self) -> LexResult<Option<Token<'a>>> {
+        // A string whose interpolated expression is never closed.
+        if let Some(Interpolation::Expr { position, .. }) = self.interpolations.pop() {
+            self.interpolations.clear();
+            return Err(SyntaxError::UnterminatedString(position));
+        }
+
         Ok(Some(self.make_token(TokenType::EOF, self.source.len())))
     }
 
@@ -155,12 +268,16 @@ impl<'a> Lexer<'a> {
     }
 
     fn token_contents(&mut self, start: usize) -> &'a str {
-        let end = self
-            .chars
+        let end = self.offset();
+        self.source[start..end].trim_end()
+    }
+
+    /// The offset of the next character in the source.
+    fn offset(&mut self) -> usize {
+        self.chars
             .peek()
             .map(|&(i, _)| i)
-            .unwrap_or(self.source.len());
-        &self.source[start..end].trim_end()
+            .unwrap_or(self.source.len())
     }
 
     fn skip_whitespace(&mut self) {
@@ -203,13 +320,20 @@ impl<'a> Lexer<'a> {
     }
 
     fn check(&mut self, c: char) -> LexResult<bool> {
+        let position = self.end_position();
         self.peek()
             .map(|p| p == c)
-            .ok_or(SyntaxError::UnexpectedEOF)
+            .ok_or(SyntaxError::UnexpectedEOF(position))
+    }
+
+    fn end_position(&self) -> Position {
+        Position::new(self.source.len(), self.source.len(), self.line)
     }
 
-    fn peek_next(&mut self) -> Option<char> {
-        self.chars.nth(1).map(|(_, c)| c)
+    fn peek_next(&self) -> Option<char> {
+        let mut chars = self.chars.clone();
+        chars.next();
+        chars.next().map(|(_, c)| c)
     }
 
     fn peek(&mut self) -> Option<char> {
@@ -256,6 +380,29 @@ mod tests {
         assert_eq!(expect, actual);
     }
 
+    #[test]
+    fn lex_interpolation() {
+        let expect = vec![
+            Token::new(TokenType::Interpolation, "Hi ", Position::new(1, 4, 1)),
+            Token::new(TokenType::Identifier, "name", Position::new(5, 9, 1)),
+            Token::new(TokenType::InterpolationEnd, "!", Position::new(9, 10, 1)),
+            Token::new(TokenType::Interpolation, "", Position::new(13, 13, 1)),
+            Token::new(TokenType::LeftBrace, "{", Position::new(15, 16, 1)),
+            Token::new(TokenType::RightBrace, "}", Position::new(16, 17, 1)),
+            Token::new(
+                TokenType::InterpolationEnd,
+                " \\$",
+                Position::new(18, 21, 1),
+            ),
+            Token::new(TokenType::EOF, "", Position::new(22, 22, 1)),
+        ];
+
+        let source = r#""Hi $name!" "${{}} \$""#;
+
+        let actual = lex(source).unwrap();
+        assert_eq!(expect, actual);
+    }
+
     // #[test] TODO
     // fn lex_keywords() {
     //     let expect = vec![
@@ -282,6 +429,37 @@ mod tests {
     //     assert_eq!(expect, actual);
     // }
 
+    #[test]
+    fn lex_logical_operators() {
+        let expect = vec![
+            Token::new(TokenType::And, "and", Position::new(0, 3, 1)),
+            Token::new(TokenType::And, "&&", Position::new(4, 6, 1)),
+            Token::new(TokenType::Or, "or", Position::new(7, 9, 1)),
+            Token::new(TokenType::Or, "||", Position::new(10, 12, 1)),
+            Token::new(TokenType::EOF, "", Position::new(12, 12, 1)),
+        ];
+
+        let source = r#"and && or ||"#;
+
+        let actual = lex(source).unwrap();
+        assert_eq!(expect, actual);
+    }
+
+    #[test]
+    fn lex_range() {
+        let expect = vec![
+            Token::new(TokenType::Number, "0", Position::new(0, 1, 1)),
+            Token::new(TokenType::DotDot, "..", Position::new(1, 3, 1)),
+            Token::new(TokenType::Number, "10.5", Position::new(3, 7, 1)),
+            Token::new(TokenType::EOF, "", Position::new(7, 7, 1)),
+        ];
+
+        let source = r#"0..10.5"#;
+
+        let actual = lex(source).unwrap();
+        assert_eq!(expect, actual);
+    }
+
     #[test]
     fn lex_comments() {
         let expect = vec![
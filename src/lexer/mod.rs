@@ -1,4 +1,4 @@
-use crate::lexer::error::LexResult;
+use crate::lexer::error::SyntaxError;
 use crate::lexer::lexer::Lexer;
 use crate::lexer::token::{Token, TokenType};
 
@@ -6,20 +6,31 @@ pub mod error;
 mod lexer;
 pub mod token;
 
-pub fn lex(source: &str) -> LexResult<Vec<Token>> {
+/// Lexes the whole source. Lexing continues after an error so every
+/// error in the source is reported.
+pub fn lex(source: &str) -> Result<Vec<Token<'_>>, Vec<SyntaxError>> {
     let mut lexer = Lexer::new(source);
 
     let mut tokens = vec![];
+    let mut errors = vec![];
     // TODO: Loop?
     loop {
-        if let Some(token) = lexer.read_token()? {
-            if let TokenType::EOF = token.token_type() {
+        match lexer.read_token() {
+            Ok(Some(token)) => {
+                if let TokenType::EOF = token.token_type() {
+                    tokens.push(token);
+                    break;
+                }
                 tokens.push(token);
-                break;
             }
-            tokens.push(token);
+            Ok(None) => {}
+            Err(error) => errors.push(error),
         }
     }
 
-    Ok(tokens)
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
 }
@@ -1,4 +1,7 @@
-#[derive(Debug, PartialEq)]
+use std::fmt;
+use std::fmt::Formatter;
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token<'a> {
     token_type: TokenType,
     source: &'a str,
@@ -38,9 +41,11 @@ pub enum TokenType {
     RightBracket,
     Comma,
     Dot,
+    DotDot,
     Minus,
     Plus,
     Star,
+    Underscore,
 
     // One or two character tokens
     Bang,
@@ -53,51 +58,162 @@ pub enum TokenType {
     GreaterThanEqual,
     Slash,
     Semicolon,
+    Colon,
+    FatArrow,
 
     // Literals
     String,
+    /// The start of a string up to its first interpolated expression, the
+    /// expression's tokens follow it.
+    Interpolation,
+    /// The part of a string between two interpolated expressions.
+    InterpolationMiddle,
+    /// The rest of a string after its last interpolated expression.
+    InterpolationEnd,
     Number,
 
     // Keywords
     Let,
+    Val,
+    Var,
     True,
     False,
+    Nil,
     Fun,
+    Def,
+    Do,
+    End,
     While,
     For,
     If,
     Else,
+    In,
     Print,
     Return,
+    Break,
+    Continue,
+    Class,
+    SelfValue,
+    Trait,
+    Impl,
+    Enum,
+    Match,
+    And,
+    Or,
 
     Identifier,
 
     EOF,
 }
 
+/// Every reserved word and the token it lexes to.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("let", TokenType::Let),
+    ("val", TokenType::Val),
+    ("var", TokenType::Var),
+    ("true", TokenType::True),
+    ("false", TokenType::False),
+    ("nil", TokenType::Nil),
+    ("fun", TokenType::Fun),
+    ("def", TokenType::Def),
+    ("do", TokenType::Do),
+    ("end", TokenType::End),
+    ("while", TokenType::While),
+    ("for", TokenType::For),
+    ("if", TokenType::If),
+    ("else", TokenType::Else),
+    ("in", TokenType::In),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("break", TokenType::Break),
+    ("continue", TokenType::Continue),
+    ("class", TokenType::Class),
+    ("self", TokenType::SelfValue),
+    ("trait", TokenType::Trait),
+    ("impl", TokenType::Impl),
+    ("enum", TokenType::Enum),
+    ("match", TokenType::Match),
+    ("and", TokenType::And),
+    ("or", TokenType::Or),
+];
+
 pub trait ToKeyword {
     fn to_keyword(self) -> Option<TokenType>;
 }
 
 impl ToKeyword for &str {
     fn to_keyword(self) -> Option<TokenType> {
-        Some(match self {
-            "let" => TokenType::Let,
-            "true" => TokenType::True,
-            "false" => TokenType::False,
-            "fun" => TokenType::Fun,
-            "while" => TokenType::While,
-            "for" => TokenType::For,
-            "if" => TokenType::If,
-            "else" => TokenType::Else,
-            "print" => TokenType::Print,
-            "return" => TokenType::Return,
-            _ => return None,
-        })
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == self)
+            .map(|(_, token_type)| token_type.clone())
+    }
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Literals and identifiers also show what was written.
+        match self.token_type {
+            TokenType::Identifier => write!(f, "identifier '{}'", self.source),
+            TokenType::Number => write!(f, "number {}", self.source),
+            TokenType::String | TokenType::Interpolation => {
+                write!(f, "string \"{}\"", self.source)
+            }
+            TokenType::InterpolationMiddle | TokenType::InterpolationEnd => {
+                write!(f, "{}", self.token_type)
+            }
+            _ => write!(f, "{}", self.token_type),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            TokenType::LeftParen => "(",
+            TokenType::RightParen => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::LeftBracket => "[",
+            TokenType::RightBracket => "]",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::DotDot => "..",
+            TokenType::Minus => "-",
+            TokenType::Plus => "+",
+            TokenType::Star => "*",
+            TokenType::Underscore => "_",
+            TokenType::Bang => "!",
+            TokenType::BangEqual => "!=",
+            TokenType::Equal => "=",
+            TokenType::EqualEqual => "==",
+            TokenType::LessThan => "<",
+            TokenType::LessThanEqual => "<=",
+            TokenType::GreaterThan => ">",
+            TokenType::GreaterThanEqual => ">=",
+            TokenType::Slash => "/",
+            TokenType::Semicolon => ";",
+            TokenType::Colon => ":",
+            TokenType::FatArrow => "=>",
+            TokenType::String | TokenType::Interpolation => return write!(f, "string"),
+            TokenType::InterpolationMiddle | TokenType::InterpolationEnd => {
+                return write!(f, "end of interpolation")
+            }
+            TokenType::Number => return write!(f, "number"),
+            TokenType::Identifier => return write!(f, "identifier"),
+            TokenType::EOF => return write!(f, "end of file"),
+            keyword => KEYWORDS
+                .iter()
+                .find(|(_, token_type)| token_type == keyword)
+                .map(|(keyword, _)| *keyword)
+                .unwrap_or("?"),
+        };
+
+        write!(f, "'{}'", symbol)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Position {
     start: usize,
     end: usize,
@@ -120,4 +236,10 @@ impl Position {
     pub fn line(&self) -> &usize {
         &self.line
     }
+
+    /// Returns a position spanning from the start of this position to the
+    /// end of `other`.
+    pub fn to(&self, other: &Position) -> Position {
+        Position::new(self.start, other.end, self.line)
+    }
 }
@@ -1,45 +1,49 @@
+#![allow(clippy::module_inception, clippy::upper_case_acronyms)]
+
+use crate::diagnostic::render::Renderer;
+use crate::vm::error::InterpretError;
 use crate::vm::interpret;
 use std::io;
+use std::io::IsTerminal;
 
-mod compiler;
-mod lexer;
-mod parser;
-mod vm;
+pub mod compiler;
+pub mod diagnostic;
+pub mod lexer;
+pub mod parser;
+pub mod repl;
+pub mod typechecker;
+pub mod vm;
 
 pub fn interpret_(s: &str) {
-    interpret(s);
+    if let Err(error) = interpret(s) {
+        report(&error, s, "script");
+    }
 }
 
 pub fn run_repl() {
-    loop {
-        interpret(&read_line());
-    }
+    repl::run();
 }
 
-fn read_line() -> String {
-    let mut input = String::new();
-    match io::stdin().read_line(&mut input) {
-        Ok(_) => {
-            trim_newline(&mut input);
-            input
-        }
-        Err(error) => {
-            panic!("error: {}", error);
+/// Prints the error to stderr against the source it came from, `name` is
+/// the file the source was read from.
+pub fn report(error: &InterpretError, source: &str, name: &str) {
+    let renderer = Renderer::new(source, name).colored(io::stderr().is_terminal());
+    match error {
+        InterpretError::Compile(diagnostics) => {
+            for diagnostic in diagnostics {
+                eprintln!("{}\n", renderer.render(diagnostic));
+            }
         }
-    }
-}
-
-fn trim_newline(s: &mut String) {
-    if s.ends_with('\n') {
-        s.pop();
-        if s.ends_with('\r') {
-            s.pop();
+        InterpretError::Runtime(traceback) => {
+            eprintln!("{}\n", renderer.render(&traceback.to_diagnostic(source)));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::diagnostic::render::Renderer;
+    use crate::vm::error::InterpretError;
     use crate::vm::interpret_with_stdout;
     use regex::Regex;
     use std::fs;
@@ -87,21 +91,56 @@ mod tests {
         TestResult::Ok
     }
 
-    fn execute(source: &str) -> (Vec<String>, TestResult) {
+    /// The line and message of every expected error. Compile errors are
+    /// expected on the line of their comment unless it names another line.
+    fn extract_errors(source: &str) -> Vec<(usize, String)> {
+        let compile_error = Regex::new(r"// (\[line (\d+)\] )?Error: (.+)").unwrap();
+        let runtime_error = Regex::new(r"// expect runtime error: (.+)").unwrap();
+
+        let mut errors = vec![];
+        for (i, line) in source.lines().enumerate() {
+            if let Some(caps) = compile_error.captures(line) {
+                let line = caps.get(2).map_or(i + 1, |l| l.as_str().parse().unwrap());
+                errors.push((line, caps[3].to_owned()));
+            }
+            if let Some(caps) = runtime_error.captures(line) {
+                errors.push((i + 1, caps[1].to_owned()));
+            }
+        }
+
+        errors
+    }
+
+    fn execute(source: &str) -> (Vec<String>, TestResult, Vec<(usize, String)>) {
         let mut output = vec![];
         let cursor = Cursor::new(&mut output);
 
+        let renderer = Renderer::new(source, "test");
+        let mut errors = vec![];
         let result = match interpret_with_stdout(source, cursor) {
             Ok(_) => TestResult::Ok,
-            Err(err) => {
-                println!("Runtime error: {:?}", err);
+            Err(InterpretError::Compile(diagnostics)) => {
+                for diagnostic in diagnostics {
+                    println!("{}", renderer.render(&diagnostic));
+                    errors.push((diagnostic.line(), diagnostic.message().to_string()));
+                }
+                TestResult::CompileError
+            }
+            Err(InterpretError::Runtime(err)) => {
+                println!("{}", renderer.render(&err.to_diagnostic(source)));
+                let line = err.frames().first().map(|frame| frame.line()).unwrap_or(0);
+                errors.push((line, err.error().to_string()));
                 TestResult::RuntimeError
             }
         };
 
         let output = String::from_utf8(output).unwrap();
 
-        (output.lines().map(|l| l.to_owned()).collect(), result)
+        (
+            output.lines().map(|l| l.to_owned()).collect(),
+            result,
+            errors,
+        )
     }
 
     fn harness(source: &str) {
@@ -109,9 +148,10 @@ mod tests {
 
         let expected_result = extract_expects(source);
 
-        let (output, result) = execute(source);
+        let (output, result, errors) = execute(source);
         assert_eq!(expects, output);
         assert_eq!(expected_result, result);
+        assert_eq!(extract_errors(source), errors);
     }
 
     fn run_test_file(path: String) {
@@ -121,6 +161,101 @@ mod tests {
         }
     }
 
+    #[test]
+    fn runtime_error_traceback() {
+        let source = r#"
+def inner()
+    return missing
+end
+
+def outer()
+    return inner()
+end
+
+outer()
+"#;
+
+        match interpret_with_stdout(source, Cursor::new(vec![])) {
+            Err(InterpretError::Runtime(traceback)) => assert_eq!(
+                "Undefined variable 'missing'.\n\
+                 [line 3] in inner()\n\
+                 [line 7] in outer()\n\
+                 [line 10] in script",
+                traceback.to_string()
+            ),
+            other => panic!("Expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn runtime_error_rendered() {
+        let source = "val a = 1\nprint(a + \"b\")\n";
+
+        match interpret_with_stdout(source, Cursor::new(vec![])) {
+            Err(InterpretError::Runtime(traceback)) => assert_eq!(
+                "error: Unsupported operand types for '+': number and string.\n \
+                 --> test:2:1\n  \
+                 |\n\
+                 2 | print(a + \"b\")\n  \
+                 | ^^^^^^^^^^^^^^\n  \
+                 = help: only two strings can be concatenated\n  \
+                 = note: [line 2] in script",
+                Renderer::new(source, "test").render(&traceback.to_diagnostic(source))
+            ),
+            other => panic!("Expected a runtime error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_type_rendered() {
+        let source = "val x: Foo = 1\n";
+
+        match interpret_with_stdout(source, Cursor::new(vec![])) {
+            Err(InterpretError::Compile(diagnostics)) => assert_eq!(
+                "error: Unknown type 'Foo'.\n \
+                 --> test:1:8\n  \
+                 |\n\
+                 1 | val x: Foo = 1\n  \
+                 |        ^^^\n  \
+                 = help: use number, bool, string, nil, list, map, range, function, any or the \
+                 name of a class, enum, trait or type parameter",
+                Renderer::new(source, "test").render(&diagnostics[0])
+            ),
+            other => panic!("Expected a compile error, got {:?}", other),
+        }
+    }
+
+    fn compile_errors(source: &str) -> Vec<String> {
+        match interpret_with_stdout(source, Cursor::new(vec![])) {
+            Err(InterpretError::Compile(diagnostics)) => diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.message().to_string())
+                .collect(),
+            other => panic!("Expected a compile error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn too_many_constants() {
+        let source: String = (0..300).map(|i| format!("print({})\n", i)).collect();
+
+        assert_eq!(
+            Some("Too many constants in one chunk."),
+            compile_errors(&source).first().map(String::as_str)
+        );
+    }
+
+    #[test]
+    fn too_many_locals() {
+        let locals: String = (0..300).map(|i| format!("val a{} = nil\n", i)).collect();
+        let source = format!("def f()\n{}end\n", locals);
+
+        assert_eq!(
+            Some("Too many local variables in function."),
+            compile_errors(&source).first().map(String::as_str)
+        );
+    }
+
     #[test]
     fn run_tests() {
         // Runs every test in the test folder.
@@ -1,12 +1,122 @@
-use untitledlang::{interpret_, run_repl};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process::exit;
+use untitledlang::compiler::{compile, disassemble};
+use untitledlang::vm::error::InterpretError;
+use untitledlang::{report, run_repl};
+
+// Exit codes from sysexits.h.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+
+const USAGE: &str = "\
+Usage: untitledlang [command] [file]
+
+Commands:
+    run <file>      Compile and run a script (the default command)
+    check <file>    Compile a script and report any errors
+    disasm <file>   Print the bytecode of a script
+    repl            Start an interactive session (the default without arguments)
+    help            Print this message
+
+Scripts are read from stdin when the file is '-' or left out.";
 
 fn main() {
-    let source = r#"
-    fun foo() {
-        print 10;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let code = match args.as_slice() {
+        [] | ["repl"] => {
+            run_repl();
+            0
+        }
+        ["help"] | ["-h"] | ["--help"] => {
+            println!("{}", USAGE);
+            0
+        }
+        ["run"] => run("-"),
+        ["run", path] => run(path),
+        ["check"] => check("-"),
+        ["check", path] => check(path),
+        ["disasm"] => disasm("-"),
+        ["disasm", path] => disasm(path),
+        [path] if !is_command(path) => run(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            EX_USAGE
+        }
+    };
+
+    exit(code);
+}
+
+fn is_command(arg: &str) -> bool {
+    matches!(arg, "run" | "check" | "disasm" | "repl")
+}
+
+fn run(path: &str) -> i32 {
+    with_source(path, |source, name| {
+        match untitledlang::vm::interpret(source) {
+            Ok(_) => 0,
+            Err(error) => {
+                report(&error, source, name);
+                exit_code(&error)
+            }
+        }
+    })
+}
+
+fn check(path: &str) -> i32 {
+    with_source(path, |source, name| match compile(source) {
+        Ok(_) => 0,
+        Err(diagnostics) => {
+            let error = InterpretError::Compile(diagnostics);
+            report(&error, source, name);
+            exit_code(&error)
+        }
+    })
+}
+
+fn disasm(path: &str) -> i32 {
+    with_source(path, |source, name| match compile(source) {
+        Ok(fun) => {
+            print!("{}", disassemble(&fun));
+            0
+        }
+        Err(diagnostics) => {
+            let error = InterpretError::Compile(diagnostics);
+            report(&error, source, name);
+            exit_code(&error)
+        }
+    })
+}
+
+fn exit_code(error: &InterpretError) -> i32 {
+    match error {
+        InterpretError::Compile(_) => EX_DATAERR,
+        InterpretError::Runtime(_) => EX_SOFTWARE,
+    }
+}
+
+/// Reads the script at `path`, or stdin for `-`, and hands it to `f` along
+/// with the name to report errors against.
+fn with_source(path: &str, f: impl FnOnce(&str, &str) -> i32) -> i32 {
+    let (source, name) = if path == "-" {
+        let mut source = String::new();
+        let source = io::stdin().read_to_string(&mut source).map(|_| source);
+        (source, "<stdin>")
+    } else {
+        (fs::read_to_string(path), path)
+    };
+
+    match source {
+        Ok(source) => f(&source, name),
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            EX_NOINPUT
+        }
     }
-    foo();
-    "#;
-    interpret_(source);
-    // run_repl();
 }
@@ -1,11 +1,11 @@
-use crate::lexer::token::TokenType;
+use crate::lexer::token::{Position, Token, TokenType};
 use crate::parser::error::{ParseResult, ParserError};
 
 pub type ModuleAst = Vec<Expr>;
 pub type BlockDecl = Vec<Expr>;
 
 #[derive(PartialEq, Debug)]
-pub enum Expr {
+pub enum ExprKind {
     Grouping {
         expr: Box<Expr>,
     },
@@ -20,7 +20,9 @@ pub enum Expr {
     },
     LetAssign {
         ident: Identifier,
+        type_: Option<TypeExpr>,
         initializer: Box<Expr>,
+        mutable: bool,
     },
     LetGet {
         ident: Identifier,
@@ -33,14 +35,73 @@ pub enum Expr {
         ident: Identifier,
         decl: FunDecl,
     },
+    Class {
+        ident: Identifier,
+        decl: ClassDecl,
+    },
+    Method {
+        class: Identifier,
+        ident: Identifier,
+        decl: FunDecl,
+    },
+    Trait {
+        ident: Identifier,
+        methods: Vec<FunSignature>,
+    },
+    Enum {
+        ident: Identifier,
+        type_params: Vec<TypeParam>,
+        variants: Vec<VariantDecl>,
+    },
+    Impl {
+        trait_: Identifier,
+        class: Identifier,
+        methods: Vec<(Identifier, FunDecl)>,
+    },
+    GetProperty {
+        target: Box<Expr>,
+        ident: Identifier,
+    },
+    SetProperty {
+        target: Box<Expr>,
+        ident: Identifier,
+        expr: Box<Expr>,
+    },
+    SelfValue,
     Call {
         callee: Box<Expr>,
         args: Vec<Expr>,
     },
+    List {
+        elements: Vec<Expr>,
+    },
+    Map {
+        entries: Vec<(Expr, Expr)>,
+    },
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+    },
+    SetIndex {
+        target: Box<Expr>,
+        index: Box<Expr>,
+        expr: Box<Expr>,
+    },
     While {
         condition: Box<Expr>,
         body: Box<Expr>,
     },
+    For {
+        init: Option<Box<Expr>>,
+        condition: Option<Box<Expr>>,
+        step: Option<Box<Expr>>,
+        body: Box<Expr>,
+    },
+    ForIn {
+        ident: Identifier,
+        iterable: Box<Expr>,
+        body: Box<Expr>,
+    },
     IfElse {
         condition: Box<Expr>,
         then: BlockDecl,
@@ -55,95 +116,284 @@ pub enum Expr {
     Return {
         expr: Option<Box<Expr>>,
     },
+    Match {
+        expr: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
+    /// A string with interpolated expressions, the value of each part is
+    /// turned into a string and the results are joined.
+    Interpolation {
+        parts: Vec<Expr>,
+    },
+    Break,
+    Continue,
     Literal(LiteralExpr),
 }
 
+#[derive(Debug)]
+pub struct Expr {
+    kind: ExprKind,
+    position: Position,
+}
+
 impl Expr {
+    pub fn new(kind: ExprKind) -> Self {
+        Expr {
+            kind,
+            position: Position::default(),
+        }
+    }
+
+    /// Sets the source position the expression was parsed from.
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn kind(&self) -> &ExprKind {
+        &self.kind
+    }
+
+    pub fn into_kind(self) -> ExprKind {
+        self.kind
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
     pub fn grouping(expr: Expr) -> Self {
-        Expr::Grouping {
+        Expr::new(ExprKind::Grouping {
             expr: Box::new(expr),
-        }
+        })
     }
 
     pub fn binary(left: Expr, op: BinaryOperator, right: Expr) -> Self {
-        Expr::Binary {
+        Expr::new(ExprKind::Binary {
             left: Box::new(left),
             op,
             right: Box::new(right),
-        }
+        })
     }
 
     pub fn unary(op: UnaryOperator, expr: Expr) -> Self {
-        Expr::Unary {
+        Expr::new(ExprKind::Unary {
             op,
             expr: Box::new(expr),
-        }
+        })
     }
 
     pub fn let_assign(ident: Identifier, initializer: Expr) -> Self {
-        Expr::LetAssign {
+        Expr::binding(ident, None, initializer, true)
+    }
+
+    pub fn val_assign(ident: Identifier, initializer: Expr) -> Self {
+        Expr::binding(ident, None, initializer, false)
+    }
+
+    /// A `let`, `var` or `val` binding, optionally annotated with a type.
+    pub fn binding(
+        ident: Identifier,
+        type_: Option<TypeExpr>,
+        initializer: Expr,
+        mutable: bool,
+    ) -> Self {
+        Expr::new(ExprKind::LetAssign {
             ident,
+            type_,
             initializer: Box::new(initializer),
-        }
+            mutable,
+        })
     }
 
     pub fn let_get(ident: Identifier) -> Self {
-        Expr::LetGet { ident }
+        Expr::new(ExprKind::LetGet { ident })
     }
 
     pub fn let_set(ident: Identifier, expr: Expr) -> Self {
-        Expr::LetSet {
+        Expr::new(ExprKind::LetSet {
             ident,
             expr: Box::new(expr),
-        }
+        })
     }
 
     pub fn fun(ident: Identifier, decl: FunDecl) -> Self {
-        Expr::Fun { ident, decl }
+        Expr::new(ExprKind::Fun { ident, decl })
+    }
+
+    pub fn class(ident: Identifier, decl: ClassDecl) -> Self {
+        Expr::new(ExprKind::Class { ident, decl })
+    }
+
+    pub fn method(class: Identifier, ident: Identifier, decl: FunDecl) -> Self {
+        Expr::new(ExprKind::Method { class, ident, decl })
+    }
+
+    pub fn trait_(ident: Identifier, methods: Vec<FunSignature>) -> Self {
+        Expr::new(ExprKind::Trait { ident, methods })
+    }
+
+    pub fn enum_(ident: Identifier, variants: Vec<VariantDecl>) -> Self {
+        Expr::generic_enum(ident, vec![], variants)
+    }
+
+    pub fn generic_enum(
+        ident: Identifier,
+        type_params: Vec<TypeParam>,
+        variants: Vec<VariantDecl>,
+    ) -> Self {
+        Expr::new(ExprKind::Enum {
+            ident,
+            type_params,
+            variants,
+        })
+    }
+
+    pub fn impl_(
+        trait_: Identifier,
+        class: Identifier,
+        methods: Vec<(Identifier, FunDecl)>,
+    ) -> Self {
+        Expr::new(ExprKind::Impl {
+            trait_,
+            class,
+            methods,
+        })
+    }
+
+    pub fn get_property(target: Expr, ident: Identifier) -> Self {
+        Expr::new(ExprKind::GetProperty {
+            target: Box::new(target),
+            ident,
+        })
+    }
+
+    pub fn set_property(target: Expr, ident: Identifier, expr: Expr) -> Self {
+        Expr::new(ExprKind::SetProperty {
+            target: Box::new(target),
+            ident,
+            expr: Box::new(expr),
+        })
+    }
+
+    pub fn self_value() -> Self {
+        Expr::new(ExprKind::SelfValue)
     }
 
     pub fn call(callee: Expr, args: Vec<Expr>) -> Self {
-        Expr::Call {
+        Expr::new(ExprKind::Call {
             callee: Box::new(callee),
             args,
-        }
+        })
+    }
+
+    pub fn list(elements: Vec<Expr>) -> Self {
+        Expr::new(ExprKind::List { elements })
+    }
+
+    pub fn map(entries: Vec<(Expr, Expr)>) -> Self {
+        Expr::new(ExprKind::Map { entries })
+    }
+
+    pub fn index(target: Expr, index: Expr) -> Self {
+        Expr::new(ExprKind::Index {
+            target: Box::new(target),
+            index: Box::new(index),
+        })
+    }
+
+    pub fn set_index(target: Expr, index: Expr, expr: Expr) -> Self {
+        Expr::new(ExprKind::SetIndex {
+            target: Box::new(target),
+            index: Box::new(index),
+            expr: Box::new(expr),
+        })
     }
 
     pub fn while_(condition: Expr, body: Expr) -> Self {
-        Expr::While {
+        Expr::new(ExprKind::While {
             condition: Box::new(condition),
             body: Box::new(body),
-        }
+        })
+    }
+
+    pub fn for_(
+        init: Option<Expr>,
+        condition: Option<Expr>,
+        step: Option<Expr>,
+        body: Expr,
+    ) -> Self {
+        Expr::new(ExprKind::For {
+            init: init.map(Box::new),
+            condition: condition.map(Box::new),
+            step: step.map(Box::new),
+            body: Box::new(body),
+        })
+    }
+
+    pub fn for_in(ident: Identifier, iterable: Expr, body: Expr) -> Self {
+        Expr::new(ExprKind::ForIn {
+            ident,
+            iterable: Box::new(iterable),
+            body: Box::new(body),
+        })
     }
 
     pub fn if_else(condition: Expr, then: BlockDecl, else_: Option<BlockDecl>) -> Self {
-        Expr::IfElse {
+        Expr::new(ExprKind::IfElse {
             condition: Box::new(condition),
             then,
             else_,
-        }
+        })
     }
 
     pub fn block(block: BlockDecl) -> Self {
-        Expr::Block {
+        Expr::new(ExprKind::Block {
             block: Box::new(block),
-        }
+        })
     }
 
     pub fn print(expr: Expr) -> Self {
-        Expr::Print {
+        Expr::new(ExprKind::Print {
             expr: Box::new(expr),
-        }
+        })
     }
 
     pub fn return_(expr: Option<Expr>) -> Self {
-        // TODO: Clean up.
-        let foo = match expr {
-            Some(e) => Some(Box::new(e)),
-            None => None,
-        };
+        Expr::new(ExprKind::Return {
+            expr: expr.map(Box::new),
+        })
+    }
+
+    pub fn match_(expr: Expr, arms: Vec<MatchArm>) -> Self {
+        Expr::new(ExprKind::Match {
+            expr: Box::new(expr),
+            arms,
+        })
+    }
+
+    pub fn interpolation(parts: Vec<Expr>) -> Self {
+        Expr::new(ExprKind::Interpolation { parts })
+    }
+
+    pub fn break_() -> Self {
+        Expr::new(ExprKind::Break)
+    }
+
+    pub fn continue_() -> Self {
+        Expr::new(ExprKind::Continue)
+    }
+
+    pub fn literal(literal: LiteralExpr) -> Self {
+        Expr::new(ExprKind::Literal(literal))
+    }
+}
 
-        Expr::Return { expr: foo }
+// Positions are left out so parsed trees can be compared against trees
+// built by hand.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
     }
 }
 
@@ -170,11 +420,14 @@ pub enum BinaryOperator {
     Add,
     Divide,
     Multiply,
+    And,
+    Or,
+    Range,
 }
 
 impl BinaryOperator {
-    pub fn from_token(token_type: &TokenType) -> ParseResult<BinaryOperator> {
-        Ok(match token_type {
+    pub fn from_token<'a>(token: &Token<'a>) -> ParseResult<'a, BinaryOperator> {
+        Ok(match token.token_type() {
             TokenType::Minus => BinaryOperator::Subtract,
             TokenType::Plus => BinaryOperator::Add,
             TokenType::Star => BinaryOperator::Multiply,
@@ -186,7 +439,10 @@ impl BinaryOperator {
             TokenType::LessThanEqual => BinaryOperator::LessThanEqual,
             TokenType::GreaterThan => BinaryOperator::GreaterThan,
             TokenType::GreaterThanEqual => BinaryOperator::GreaterThanEqual,
-            _ => return Err(ParserError::ExpectedBinaryOperator(token_type.clone())),
+            TokenType::And => BinaryOperator::And,
+            TokenType::Or => BinaryOperator::Or,
+            TokenType::DotDot => BinaryOperator::Range,
+            _ => return Err(ParserError::ExpectedBinaryOperator(token.clone())),
         })
     }
 }
@@ -198,31 +454,349 @@ pub enum UnaryOperator {
 }
 
 impl UnaryOperator {
-    pub fn from_token(token_type: &TokenType) -> ParseResult<UnaryOperator> {
-        Ok(match token_type {
+    pub fn from_token<'a>(token: &Token<'a>) -> ParseResult<'a, UnaryOperator> {
+        Ok(match token.token_type() {
             TokenType::Minus => UnaryOperator::Negate,
             TokenType::Bang => UnaryOperator::Not,
-            _ => return Err(ParserError::ExpectedUnaryOperator(token_type.clone())),
+            _ => return Err(ParserError::ExpectedUnaryOperator(token.clone())),
         })
     }
 }
 
+/// A type written in an annotation, like `number` or `Rectangle`.
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    kind: TypeExprKind,
+    position: Position,
+}
+
+impl TypeExpr {
+    pub fn named(ident: Identifier) -> Self {
+        TypeExpr::new(TypeExprKind::Named(ident))
+    }
+
+    pub fn generic(ident: Identifier, args: Vec<TypeExpr>) -> Self {
+        TypeExpr::new(TypeExprKind::Generic(ident, args))
+    }
+
+    fn new(kind: TypeExprKind) -> Self {
+        TypeExpr {
+            kind,
+            position: Position::default(),
+        }
+    }
+
+    /// Sets the source position the annotation was parsed from.
+    pub fn at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn kind(&self) -> &TypeExprKind {
+        &self.kind
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+}
+
+impl PartialEq for TypeExpr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum TypeExprKind {
+    Named(Identifier),
+    /// A generic type given its type arguments, like `List<number>`.
+    Generic(Identifier, Vec<TypeExpr>),
+}
+
+/// A type parameter of a generic function, class or enum. `T: Shape` only
+/// accepts types that implement the trait `Shape`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TypeParam {
+    ident: Identifier,
+    bound: Option<Identifier>,
+}
+
+impl TypeParam {
+    pub fn new(ident: Identifier, bound: Option<Identifier>) -> Self {
+        TypeParam { ident, bound }
+    }
+
+    pub fn ident(&self) -> &Identifier {
+        &self.ident
+    }
+
+    pub fn bound(&self) -> Option<&Identifier> {
+        self.bound.as_ref()
+    }
+}
+
+/// A parameter of a function, optionally annotated with a type.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Param {
+    ident: Identifier,
+    type_: Option<TypeExpr>,
+}
+
+impl Param {
+    pub fn new(ident: Identifier, type_: Option<TypeExpr>) -> Self {
+        Param { ident, type_ }
+    }
+
+    pub fn ident(&self) -> &Identifier {
+        &self.ident
+    }
+
+    pub fn type_(&self) -> Option<&TypeExpr> {
+        self.type_.as_ref()
+    }
+}
+
 #[derive(PartialEq, Debug)]
 pub struct FunDecl {
-    args: Vec<Identifier>,
+    type_params: Vec<TypeParam>,
+    args: Vec<Param>,
+    return_type: Option<TypeExpr>,
     body: BlockDecl,
 }
 
 impl FunDecl {
-    pub fn new(args: Vec<Identifier>, body: BlockDecl) -> Self {
-        FunDecl { args, body }
+    pub fn new(args: Vec<Param>, return_type: Option<TypeExpr>, body: BlockDecl) -> Self {
+        FunDecl::generic(vec![], args, return_type, body)
+    }
+
+    pub fn generic(
+        type_params: Vec<TypeParam>,
+        args: Vec<Param>,
+        return_type: Option<TypeExpr>,
+        body: BlockDecl,
+    ) -> Self {
+        FunDecl {
+            type_params,
+            args,
+            return_type,
+            body,
+        }
     }
 
-    pub fn args(&self) -> &Vec<Identifier> {
+    pub fn type_params(&self) -> &[TypeParam] {
+        &self.type_params
+    }
+
+    pub fn args(&self) -> &[Param] {
         &self.args
     }
 
-    pub fn body(self) -> BlockDecl {
+    pub fn return_type(&self) -> Option<&TypeExpr> {
+        self.return_type.as_ref()
+    }
+
+    pub fn body(&self) -> &BlockDecl {
+        &self.body
+    }
+
+    pub fn into_body(self) -> BlockDecl {
         self.body
     }
 }
+
+/// A variant of an enum and the values it carries, optionally annotated
+/// with their types.
+#[derive(PartialEq, Debug)]
+pub struct VariantDecl {
+    ident: Identifier,
+    fields: Vec<Param>,
+}
+
+impl VariantDecl {
+    pub fn new(ident: Identifier, fields: Vec<Identifier>) -> Self {
+        let fields = fields
+            .into_iter()
+            .map(|field| Param::new(field, None))
+            .collect();
+        VariantDecl::typed(ident, fields)
+    }
+
+    pub fn typed(ident: Identifier, fields: Vec<Param>) -> Self {
+        VariantDecl { ident, fields }
+    }
+
+    pub fn ident(&self) -> &Identifier {
+        &self.ident
+    }
+
+    pub fn fields(&self) -> &[Param] {
+        &self.fields
+    }
+}
+
+/// An arm of a `match`, the body runs for the first arm whose pattern
+/// matches and whose guard, if any, is true.
+#[derive(PartialEq, Debug)]
+pub struct MatchArm {
+    pattern: Pattern,
+    guard: Option<Expr>,
+    body: Expr,
+}
+
+impl MatchArm {
+    pub fn new(pattern: Pattern, guard: Option<Expr>, body: Expr) -> Self {
+        MatchArm {
+            pattern,
+            guard,
+            body,
+        }
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    pub fn guard(&self) -> Option<&Expr> {
+        self.guard.as_ref()
+    }
+
+    pub fn body(&self) -> &Expr {
+        &self.body
+    }
+
+    pub fn into_parts(self) -> (Pattern, Option<Expr>, Expr) {
+        (self.pattern, self.guard, self.body)
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Pattern {
+    /// `_`, matches anything.
+    Wildcard,
+    /// Matches any value and binds it to the name.
+    Binding(Identifier),
+    /// Matches a value equal to the literal.
+    Literal(LiteralExpr),
+    /// `Shape.Circle(r)`, matches a variant of an enum whose values match
+    /// the nested patterns.
+    Variant {
+        enum_: Identifier,
+        variant: Identifier,
+        fields: Vec<Pattern>,
+    },
+    /// `[a, b]`, matches a list of exactly that length.
+    List(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard | Pattern::Binding(_))
+    }
+}
+
+/// A method a trait requires, without a body.
+#[derive(PartialEq, Debug)]
+pub struct FunSignature {
+    ident: Identifier,
+    args: Vec<Param>,
+    return_type: Option<TypeExpr>,
+}
+
+impl FunSignature {
+    pub fn new(ident: Identifier, args: Vec<Param>, return_type: Option<TypeExpr>) -> Self {
+        FunSignature {
+            ident,
+            args,
+            return_type,
+        }
+    }
+
+    pub fn ident(&self) -> &Identifier {
+        &self.ident
+    }
+
+    pub fn args(&self) -> &[Param] {
+        &self.args
+    }
+
+    pub fn return_type(&self) -> Option<&TypeExpr> {
+        self.return_type.as_ref()
+    }
+}
+
+/// A field of a class, `var` fields can be assigned after construction.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FieldDecl {
+    ident: Identifier,
+    mutable: bool,
+    type_: Option<TypeExpr>,
+}
+
+impl FieldDecl {
+    pub fn new(ident: Identifier, mutable: bool) -> Self {
+        FieldDecl::typed(ident, mutable, None)
+    }
+
+    pub fn typed(ident: Identifier, mutable: bool, type_: Option<TypeExpr>) -> Self {
+        FieldDecl {
+            ident,
+            mutable,
+            type_,
+        }
+    }
+
+    pub fn type_(&self) -> Option<&TypeExpr> {
+        self.type_.as_ref()
+    }
+
+    pub fn ident(&self) -> &Identifier {
+        &self.ident
+    }
+
+    pub fn mutable(&self) -> bool {
+        self.mutable
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct ClassDecl {
+    type_params: Vec<TypeParam>,
+    fields: Vec<FieldDecl>,
+    methods: Vec<(Identifier, FunDecl)>,
+}
+
+impl ClassDecl {
+    pub fn new(fields: Vec<FieldDecl>, methods: Vec<(Identifier, FunDecl)>) -> Self {
+        ClassDecl::generic(vec![], fields, methods)
+    }
+
+    pub fn generic(
+        type_params: Vec<TypeParam>,
+        fields: Vec<FieldDecl>,
+        methods: Vec<(Identifier, FunDecl)>,
+    ) -> Self {
+        ClassDecl {
+            type_params,
+            fields,
+            methods,
+        }
+    }
+
+    pub fn type_params(&self) -> &[TypeParam] {
+        &self.type_params
+    }
+
+    pub fn fields(&self) -> &[FieldDecl] {
+        &self.fields
+    }
+
+    pub fn methods(&self) -> &[(Identifier, FunDecl)] {
+        &self.methods
+    }
+
+    pub fn into_methods(self) -> Vec<(Identifier, FunDecl)> {
+        self.methods
+    }
+}
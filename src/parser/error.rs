@@ -1,14 +1,78 @@
-use crate::lexer::token::TokenType;
+use crate::lexer::token::{Position, Token, TokenType};
+use std::fmt;
+use std::fmt::Formatter;
 
-pub type ParseResult<T> = std::result::Result<T, ParserError>;
+pub type ParseResult<'a, T> = std::result::Result<T, ParserError<'a>>;
 
-// TODO: Use Token not TokenType.
 #[derive(Debug)]
-pub enum ParserError {
-    Expected(TokenType, TokenType, usize),
-    Unexpected(TokenType),
-    ExpectedPrimary(TokenType),
-    ExpectedUnaryOperator(TokenType),
-    ExpectedBinaryOperator(TokenType),
+pub enum ParserError<'a> {
+    Expected(TokenType, Token<'a>),
+    Unexpected(Token<'a>),
+    ExpectedPrimary(Token<'a>),
+    ExpectedUnaryOperator(Token<'a>),
+    ExpectedBinaryOperator(Token<'a>),
+    MapAfterIdentifier(Token<'a>),
     UnexpectedEOF,
 }
+
+impl<'a> ParserError<'a> {
+    /// The offending token, `None` if the parser ran out of tokens.
+    pub fn token(&self) -> Option<&Token<'a>> {
+        match self {
+            ParserError::Expected(_, token)
+            | ParserError::Unexpected(token)
+            | ParserError::ExpectedPrimary(token)
+            | ParserError::ExpectedUnaryOperator(token)
+            | ParserError::ExpectedBinaryOperator(token)
+            | ParserError::MapAfterIdentifier(token) => Some(token),
+            ParserError::UnexpectedEOF => None,
+        }
+    }
+
+    /// The position of the offending token, `None` if the parser ran out of
+    /// tokens.
+    pub fn position(&self) -> Option<&Position> {
+        self.token().map(|token| token.position())
+    }
+
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            ParserError::Expected(TokenType::End, _) => {
+                Some("blocks opened with 'do' or 'def' are closed with 'end'".to_string())
+            }
+            ParserError::Expected(TokenType::RightParen, _) => {
+                Some("check for an unclosed '('".to_string())
+            }
+            ParserError::MapAfterIdentifier(token) => Some(format!(
+                "call '{}(...)' to create an instance",
+                token.source()
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> fmt::Display for ParserError<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::Expected(expected, found) => {
+                write!(f, "Expected {} but found {}.", expected, found)
+            }
+            ParserError::Unexpected(found) => write!(f, "Unexpected {}.", found),
+            ParserError::ExpectedPrimary(found) => {
+                write!(f, "Expected expression but found {}.", found)
+            }
+            ParserError::ExpectedUnaryOperator(found) => {
+                write!(f, "Expected unary operator but found {}.", found)
+            }
+            ParserError::ExpectedBinaryOperator(found) => {
+                write!(f, "Expected binary operator but found {}.", found)
+            }
+            ParserError::MapAfterIdentifier(token) => {
+                write!(f, "Unexpected map literal after '{}'.", token.source())
+            }
+            ParserError::UnexpectedEOF => write!(f, "Unexpected end of file."),
+        }
+    }
+}
@@ -4,16 +4,21 @@ use crate::parser::error::{ParseResult, ParserError};
 use crate::parser::parser::Parser;
 
 #[derive(PartialEq, PartialOrd)]
+#[allow(dead_code)]
 enum Precedence {
     None,
     Assign,
     // =
     Or,
+    // or ||
     And,
+    // and &&
     Equality,
     // == !=
     Comparison,
     // < <= > >=
+    Range,
+    // ..
     Term,
     // + -
     Factor,
@@ -21,7 +26,7 @@ enum Precedence {
     Unary,
     // ! -
     Call,
-    // ()
+    // () [] .
     Primary,
 }
 
@@ -29,51 +34,70 @@ impl From<&TokenType> for Precedence {
     fn from(token: &TokenType) -> Precedence {
         match token {
             TokenType::Equal => Precedence::Assign,
+            TokenType::Or => Precedence::Or,
+            TokenType::And => Precedence::And,
             TokenType::BangEqual | TokenType::EqualEqual => Precedence::Equality,
             TokenType::LessThan
             | TokenType::LessThanEqual
             | TokenType::GreaterThan
             | TokenType::GreaterThanEqual => Precedence::Comparison,
+            TokenType::DotDot => Precedence::Range,
             TokenType::Plus | TokenType::Minus => Precedence::Term,
             TokenType::Star | TokenType::Slash => Precedence::Factor,
             TokenType::Bang => Precedence::Unary,
-            TokenType::LeftParen => Precedence::Call,
+            TokenType::LeftParen | TokenType::LeftBracket => Precedence::Call,
             TokenType::Dot => Precedence::Call,
             _ => Precedence::None,
         }
     }
 }
 
-pub fn parse(parser: &mut Parser) -> ParseResult<Expr> {
+pub fn parse<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
     parse_expr(parser, Precedence::None) // TODO: Change to Precedence::Assignment?
 }
 
-fn parse_expr(parser: &mut Parser, precedence: Precedence) -> ParseResult<Expr> {
+fn parse_expr<'a>(parser: &mut Parser<'a>, precedence: Precedence) -> ParseResult<'a, Expr> {
     let mut expr = parse_prefix(parser)?;
     while !parser.is_eof()? {
         let next_precedence = Precedence::from(parser.peek_type()?);
         if precedence >= next_precedence {
             break;
         }
+
+        // A `(` or `[` starting a line begins a new expression instead of
+        // calling or indexing the one before it.
+        if matches!(
+            parser.peek_type()?,
+            TokenType::LeftParen | TokenType::LeftBracket
+        ) && parser.starts_line()?
+        {
+            break;
+        }
         expr = parse_infix(parser, expr)?;
     }
     Ok(expr)
 }
 
-fn parse_prefix(parser: &mut Parser) -> ParseResult<Expr> {
+fn parse_prefix<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
     match parser.peek_type()? {
         TokenType::Number
         | TokenType::Identifier
         | TokenType::String
         | TokenType::True
-        | TokenType::False => parse_primary(parser),
+        | TokenType::False
+        | TokenType::Nil
+        | TokenType::SelfValue => parse_primary(parser),
         TokenType::Bang | TokenType::Minus => parse_unary(parser),
         TokenType::LeftParen => parse_grouping(parser),
-        _ => Err(ParserError::Unexpected(parser.peek_type()?.clone())),
+        TokenType::LeftBracket => parse_list(parser),
+        TokenType::LeftBrace => parse_map(parser),
+        TokenType::Match => parser.parse_match(),
+        TokenType::Interpolation => parse_interpolation(parser),
+        _ => Err(ParserError::ExpectedPrimary(parser.peek()?.clone())),
     }
 }
 
-fn parse_infix(parser: &mut Parser, left: Expr) -> ParseResult<Expr> {
+fn parse_infix<'a>(parser: &mut Parser<'a>, left: Expr) -> ParseResult<'a, Expr> {
     match parser.peek_type()? {
         TokenType::BangEqual
         | TokenType::EqualEqual
@@ -84,38 +108,89 @@ fn parse_infix(parser: &mut Parser, left: Expr) -> ParseResult<Expr> {
         | TokenType::Plus
         | TokenType::Minus
         | TokenType::Star
-        | TokenType::Slash => parse_binary(parser, left),
+        | TokenType::Slash
+        | TokenType::And
+        | TokenType::Or
+        | TokenType::DotDot => parse_binary(parser, left),
         TokenType::LeftParen => parse_call(parser, left),
-        _ => Err(ParserError::Unexpected(parser.peek_type()?.clone())),
+        TokenType::LeftBracket => parse_index(parser, left),
+        TokenType::Dot => parse_property(parser, left),
+        _ => Err(ParserError::Unexpected(parser.peek()?.clone())),
     }
 }
 
-fn parse_primary(parser: &mut Parser) -> ParseResult<Expr> {
+fn parse_primary<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
     let token = parser.consume()?;
-    match token.token_type() {
-        TokenType::Number => Ok(Expr::Literal(LiteralExpr::Number(
-            token.source().parse::<f64>().unwrap(),
-        ))),
-        TokenType::String => Ok(Expr::Literal(LiteralExpr::String(
-            token.source().to_string(),
-        ))),
-        TokenType::True => Ok(Expr::Literal(LiteralExpr::True)),
-        TokenType::False => Ok(Expr::Literal(LiteralExpr::False)),
+    let position = *token.position();
+    let expr = match token.token_type() {
+        TokenType::Number => {
+            Expr::literal(LiteralExpr::Number(token.source().parse::<f64>().unwrap()))
+        }
+        TokenType::String => Expr::literal(LiteralExpr::String(unescape(token.source()))),
+        TokenType::True => Expr::literal(LiteralExpr::True),
+        TokenType::False => Expr::literal(LiteralExpr::False),
+        TokenType::Nil => Expr::literal(LiteralExpr::Nil),
+        TokenType::SelfValue => Expr::self_value(),
         TokenType::Identifier => {
             let ident = token.source().to_string();
 
-            Ok(if parser.match_(TokenType::Equal)? {
+            // `Rectangle { width: 5 }` would otherwise parse as a variable
+            // followed by an unrelated map literal. The map is skipped so
+            // parsing carries on after it.
+            if parser.is_map_start()? && !parser.starts_line()? {
+                parse_map(parser)?;
+                parser.add_error(ParserError::MapAfterIdentifier(token.clone()));
+            }
+
+            if parser.match_(TokenType::Equal)? {
                 let expr = parser.expression()?;
-                Expr::let_set(ident, expr)
-            } else {
-                Expr::let_get(ident)
-            })
+                let position = position.to(expr.position());
+                return Ok(Expr::let_set(ident, expr).at(position));
+            }
+
+            Expr::let_get(ident)
         }
-        _ => Err(ParserError::ExpectedPrimary(token.token_type().clone())),
+        _ => return Err(ParserError::ExpectedPrimary(token)),
+    };
+
+    Ok(expr.at(position))
+}
+
+/// Parses `"Hello, $name!"`, which the lexer splits into the parts of the
+/// string with the tokens of each interpolated expression in between.
+fn parse_interpolation<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
+    let start = *parser.peek()?.position();
+
+    let mut parts = vec![];
+    let mut token = parser.expect(TokenType::Interpolation)?;
+    loop {
+        if !token.source().is_empty() {
+            let part = Expr::literal(LiteralExpr::String(unescape(token.source())));
+            parts.push(part.at(*token.position()));
+        }
+        parts.push(parser.expression()?);
+
+        if !parser.check(TokenType::InterpolationMiddle)? {
+            break;
+        }
+        token = parser.consume()?;
+    }
+
+    let end = parser.expect(TokenType::InterpolationEnd)?;
+    if !end.source().is_empty() {
+        let part = Expr::literal(LiteralExpr::String(unescape(end.source())));
+        parts.push(part.at(*end.position()));
     }
+
+    Ok(Expr::interpolation(parts).at(start.to(end.position())))
+}
+
+/// The contents of a string, with `\$` written as a plain '$'.
+pub fn unescape(source: &str) -> String {
+    source.replace("\\$", "$")
 }
 
-fn parse_call(parser: &mut Parser, left: Expr) -> ParseResult<Expr> {
+fn parse_call<'a>(parser: &mut Parser<'a>, left: Expr) -> ParseResult<'a, Expr> {
     parser.expect(TokenType::LeftParen)?;
 
     let mut args = vec![];
@@ -127,32 +202,104 @@ fn parse_call(parser: &mut Parser, left: Expr) -> ParseResult<Expr> {
         }
     }
 
-    parser.expect(TokenType::RightParen)?;
+    let end = parser.expect(TokenType::RightParen)?;
+    let position = left.position().to(end.position());
+
+    Ok(Expr::call(left, args).at(position))
+}
+
+fn parse_property<'a>(parser: &mut Parser<'a>, left: Expr) -> ParseResult<'a, Expr> {
+    parser.expect(TokenType::Dot)?;
+    let end = parser.position()?;
+    let ident = parser.parse_ident()?;
+
+    if parser.match_(TokenType::Equal)? {
+        let expr = parser.expression()?;
+        let position = left.position().to(expr.position());
+        return Ok(Expr::set_property(left, ident, expr).at(position));
+    }
+
+    let position = left.position().to(&end);
+    Ok(Expr::get_property(left, ident).at(position))
+}
+
+fn parse_index<'a>(parser: &mut Parser<'a>, left: Expr) -> ParseResult<'a, Expr> {
+    parser.expect(TokenType::LeftBracket)?;
+    let index = parser.expression()?;
+    let end = parser.expect(TokenType::RightBracket)?;
+
+    if parser.match_(TokenType::Equal)? {
+        let expr = parser.expression()?;
+        let position = left.position().to(expr.position());
+        return Ok(Expr::set_index(left, index, expr).at(position));
+    }
+
+    let position = left.position().to(end.position());
+    Ok(Expr::index(left, index).at(position))
+}
+
+fn parse_list<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
+    let start = parser.expect(TokenType::LeftBracket)?;
+
+    let mut elements = vec![];
+    while !parser.check(TokenType::RightBracket)? && !parser.check(TokenType::EOF)? {
+        elements.push(parser.expression()?);
+
+        if !parser.match_(TokenType::Comma)? {
+            break;
+        }
+    }
+
+    let end = parser.expect(TokenType::RightBracket)?;
+    let position = start.position().to(end.position());
+
+    Ok(Expr::list(elements).at(position))
+}
+
+fn parse_map<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
+    let start = parser.expect(TokenType::LeftBrace)?;
+
+    let mut entries = vec![];
+    while !parser.check(TokenType::RightBrace)? && !parser.check(TokenType::EOF)? {
+        let key = parser.expression()?;
+        parser.expect(TokenType::Colon)?;
+        entries.push((key, parser.expression()?));
+
+        if !parser.match_(TokenType::Comma)? {
+            break;
+        }
+    }
+
+    let end = parser.expect(TokenType::RightBrace)?;
+    let position = start.position().to(end.position());
 
-    Ok(Expr::call(left, args))
+    Ok(Expr::map(entries).at(position))
 }
 
-fn parse_binary(parser: &mut Parser, left: Expr) -> ParseResult<Expr> {
+fn parse_binary<'a>(parser: &mut Parser<'a>, left: Expr) -> ParseResult<'a, Expr> {
     let op_token = parser.consume()?;
     let precedence = Precedence::from(op_token.token_type());
-    let op = BinaryOperator::from_token(op_token.token_type())?;
+    let op = BinaryOperator::from_token(&op_token)?;
     let right = parse_expr(parser, precedence)?;
+    let position = left.position().to(right.position());
 
-    Ok(Expr::binary(left, op, right))
+    Ok(Expr::binary(left, op, right).at(position))
 }
 
-fn parse_unary(parser: &mut Parser) -> ParseResult<Expr> {
+fn parse_unary<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
     let op_token = parser.consume()?;
-    let op = UnaryOperator::from_token(op_token.token_type())?;
+    let op = UnaryOperator::from_token(&op_token)?;
     let right = parse_expr(parser, Precedence::Unary)?;
+    let position = op_token.position().to(right.position());
 
-    Ok(Expr::unary(op, right))
+    Ok(Expr::unary(op, right).at(position))
 }
 
-fn parse_grouping(parser: &mut Parser) -> ParseResult<Expr> {
-    parser.expect(TokenType::LeftParen)?;
+fn parse_grouping<'a>(parser: &mut Parser<'a>) -> ParseResult<'a, Expr> {
+    let start = parser.expect(TokenType::LeftParen)?;
     let expr = parse(parser)?;
-    parser.expect(TokenType::RightParen)?;
+    let end = parser.expect(TokenType::RightParen)?;
+    let position = start.position().to(end.position());
 
-    Ok(Expr::grouping(expr))
+    Ok(Expr::grouping(expr).at(position))
 }
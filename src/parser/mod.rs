@@ -8,15 +8,17 @@ mod error;
 mod expr_parser;
 mod parser;
 
-type ParserResult<'a, T> = std::result::Result<T, ParserError>;
+type ParserResult<'a, T> = std::result::Result<T, Vec<ParserError<'a>>>;
 
-pub fn parse<'a>(tokens: &'a mut Vec<Token<'a>>) -> ParserResult<ModuleAst> {
+/// Parses the tokens into a module, or returns every error found in it.
+pub fn parse<'a>(tokens: &'a mut Vec<Token<'a>>) -> ParserResult<'a, ModuleAst> {
     let mut parser = Parser::new(tokens);
+    let ast = parser.parse_module();
 
-    let mut ast = vec![];
-    while !parser.is_eof()? {
-        ast.push(parser.parse_top_level_expr()?);
+    let errors = parser.into_errors();
+    if errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(errors)
     }
-
-    Ok(ast)
 }
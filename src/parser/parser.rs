@@ -1,77 +1,334 @@
-use crate::lexer::token::{Token, TokenType};
+use crate::lexer::token::{Position, Token, TokenType};
 use crate::parser::ast::*;
 use crate::parser::error::{ParseResult, ParserError};
 use crate::parser::expr_parser;
 
 pub struct Parser<'a> {
     tokens: &'a mut Vec<Token<'a>>,
+    errors: Vec<ParserError<'a>>,
+    line: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a mut Vec<Token<'a>>) -> Self {
         tokens.reverse();
-        Parser { tokens }
+        Parser {
+            tokens,
+            errors: vec![],
+            line: 1,
+        }
+    }
+
+    /// Parses every top-level expression in the file, errors are collected
+    /// instead of returned so the rest of the file is still checked.
+    pub fn parse_module(&mut self) -> ModuleAst {
+        match self.block_until(&[]) {
+            Ok(ast) => ast,
+            Err(error) => {
+                self.errors.push(error);
+                vec![]
+            }
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<ParserError<'a>> {
+        self.errors
     }
 
-    pub fn parse_top_level_expr(&mut self) -> ParseResult<Expr> {
+    /// Records an error that parsing can continue past.
+    pub fn add_error(&mut self, error: ParserError<'a>) {
+        self.errors.push(error);
+    }
+
+    pub fn parse_top_level_expr(&mut self) -> ParseResult<'a, Expr> {
         match self.peek_type()? {
-            TokenType::Let => self.parse_let(),
-            TokenType::Fun => self.parse_fun(),
+            TokenType::Let | TokenType::Var | TokenType::Val => self.parse_let(),
+            TokenType::Fun | TokenType::Def => self.parse_fun(),
+            TokenType::Class => self.parse_class(),
+            TokenType::Trait => self.parse_trait(),
+            TokenType::Enum => self.parse_enum(),
+            TokenType::Impl => self.parse_impl(),
             TokenType::While => self.parse_while(),
             TokenType::For => self.parse_for(),
             TokenType::If => self.parse_if(),
             TokenType::Print => self.parse_print(),
-            TokenType::LeftBrace => self.parse_block(),
+            TokenType::LeftBrace if !self.is_map_start()? => self.parse_block(),
             TokenType::Return => self.parse_return(),
+            TokenType::Break | TokenType::Continue => self.parse_loop_jump(),
             _ => self.parse_expr_statement(),
         }
     }
 
-    fn parse_let(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::Let)?;
+    fn parse_let(&mut self) -> ParseResult<'a, Expr> {
+        // let and var bindings are mutable, val bindings are not.
+        let token = self.consume()?;
+        let mutable = match token.token_type() {
+            TokenType::Let | TokenType::Var => true,
+            TokenType::Val => false,
+            _ => return Err(ParserError::Unexpected(token)),
+        };
 
+        let position = self.position()?;
         let ident = self.parse_ident()?;
+        let type_ = self.parse_annotation()?;
 
         let initializer = if self.match_(TokenType::Equal)? {
             self.parse_expr_statement()?
         } else {
             self.expect(TokenType::Semicolon)?;
-            Expr::Literal(LiteralExpr::Nil)
+            Expr::literal(LiteralExpr::Nil).at(position)
         };
 
-        Ok(Expr::let_assign(ident, initializer))
+        Ok(Expr::binding(ident, type_, initializer, mutable).at(position))
     }
 
-    fn parse_fun(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::Fun)?;
+    fn parse_fun(&mut self) -> ParseResult<'a, Expr> {
+        if !self.match_(TokenType::Def)? {
+            self.expect(TokenType::Fun)?;
+        }
 
+        // def (Rectangle) area() ... end defines a method of Rectangle.
+        let class = if self.match_(TokenType::LeftParen)? {
+            let class = self.parse_ident()?;
+            self.expect(TokenType::RightParen)?;
+            Some(class)
+        } else {
+            None
+        };
+
+        let position = self.position()?;
+        let (ident, fun_decl) = self.parse_fun_decl()?;
+
+        let expr = match class {
+            Some(class) => Expr::method(class, ident, fun_decl),
+            None => Expr::fun(ident, fun_decl),
+        };
+        Ok(expr.at(position))
+    }
+
+    /// Parses the name, arguments and body of a function.
+    fn parse_fun_decl(&mut self) -> ParseResult<'a, (Identifier, FunDecl)> {
         let ident = self.parse_ident()?;
-        let args = self.parse_args()?;
+        let type_params = self.parse_type_params()?;
+        let args = self.parse_params()?;
+        let return_type = self.parse_annotation()?;
+
+        // fun foo() { ... } or def foo() ... end
+        let body = if self.check(TokenType::LeftBrace)? {
+            self.block()?
+        } else {
+            self.keyword_block()?
+        };
+
+        Ok((
+            ident,
+            FunDecl::generic(type_params, args, return_type, body),
+        ))
+    }
+
+    /// Parses `class Name (var a, val b)`, or a class whose fields and
+    /// methods follow its name up to `end`.
+    fn parse_class(&mut self) -> ParseResult<'a, Expr> {
+        self.expect(TokenType::Class)?;
+
+        let position = self.position()?;
+        let ident = self.parse_ident()?;
+        let type_params = self.parse_type_params()?;
+
+        let mut fields = vec![];
+        let mut methods = vec![];
+        if self.match_(TokenType::LeftParen)? {
+            while !self.check(TokenType::RightParen)? && !self.is_eof()? {
+                fields.push(self.parse_field()?);
+
+                if !self.match_(TokenType::Comma)? {
+                    break;
+                }
+            }
+            self.expect(TokenType::RightParen)?;
+        } else {
+            while !self.check(TokenType::End)? && !self.is_eof()? {
+                if self.match_(TokenType::Def)? || self.match_(TokenType::Fun)? {
+                    methods.push(self.parse_fun_decl()?);
+                } else {
+                    fields.push(self.parse_field()?);
+                    self.match_(TokenType::Semicolon)?;
+                }
+            }
+            self.expect(TokenType::End)?;
+        }
+
+        let decl = ClassDecl::generic(type_params, fields, methods);
+        Ok(Expr::class(ident, decl).at(position))
+    }
+
+    /// Parses `trait Name` followed by the signatures of the methods it
+    /// requires, up to `end`.
+    fn parse_trait(&mut self) -> ParseResult<'a, Expr> {
+        self.expect(TokenType::Trait)?;
+
+        let position = self.position()?;
+        let ident = self.parse_ident()?;
+
+        let mut methods = vec![];
+        while !self.check(TokenType::End)? && !self.is_eof()? {
+            if !self.match_(TokenType::Def)? {
+                self.expect(TokenType::Fun)?;
+            }
+            let name = self.parse_ident()?;
+            let args = self.parse_params()?;
+            methods.push(FunSignature::new(name, args, self.parse_annotation()?));
+            self.match_(TokenType::Semicolon)?;
+        }
+        self.expect(TokenType::End)?;
+
+        Ok(Expr::trait_(ident, methods).at(position))
+    }
+
+    /// Parses `enum Name` followed by its variants up to `end`, a variant
+    /// names the values it carries like `Circle(radius)`.
+    fn parse_enum(&mut self) -> ParseResult<'a, Expr> {
+        self.expect(TokenType::Enum)?;
+
+        let position = self.position()?;
+        let ident = self.parse_ident()?;
+        let type_params = self.parse_type_params()?;
+
+        let mut variants = vec![];
+        while !self.check(TokenType::End)? && !self.is_eof()? {
+            let name = self.parse_ident()?;
+            let fields = if self.check(TokenType::LeftParen)? {
+                self.parse_params()?
+            } else {
+                vec![]
+            };
+            variants.push(VariantDecl::typed(name, fields));
+
+            if !self.match_(TokenType::Comma)? {
+                break;
+            }
+        }
+        self.expect(TokenType::End)?;
 
-        let body = self.block()?;
-        let fun_decl = FunDecl::new(args, body);
+        Ok(Expr::generic_enum(ident, type_params, variants).at(position))
+    }
 
-        Ok(Expr::fun(ident, fun_decl))
+    /// Parses `impl Trait for Class` followed by the methods implementing
+    /// the trait, up to `end`.
+    fn parse_impl(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::Impl)?.position();
+
+        let trait_ = self.parse_ident()?;
+        self.expect(TokenType::For)?;
+        let class = self.parse_ident()?;
+
+        let mut methods = vec![];
+        while !self.check(TokenType::End)? && !self.is_eof()? {
+            if !self.match_(TokenType::Def)? {
+                self.expect(TokenType::Fun)?;
+            }
+            methods.push(self.parse_fun_decl()?);
+        }
+        self.expect(TokenType::End)?;
+
+        Ok(Expr::impl_(trait_, class, methods).at(position))
     }
 
-    fn parse_while(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::While)?;
+    /// Parses a field, only fields declared with `var` or `let` can be
+    /// assigned to.
+    fn parse_field(&mut self) -> ParseResult<'a, FieldDecl> {
+        let mutable = match self.peek_type()? {
+            TokenType::Var | TokenType::Let => {
+                self.consume()?;
+                true
+            }
+            TokenType::Val => {
+                self.consume()?;
+                false
+            }
+            _ => false,
+        };
+
+        let ident = self.parse_ident()?;
+        Ok(FieldDecl::typed(ident, mutable, self.parse_annotation()?))
+    }
+
+    fn parse_while(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::While)?.position();
 
         let cond = self.expression()?;
-        let body = self.parse_block()?;
+        let body = self.parse_loop_body()?;
 
-        Ok(Expr::while_(cond, body))
+        Ok(Expr::while_(cond, body).at(position))
     }
 
-    fn parse_for(&mut self) -> ParseResult<Expr> {
-        todo!()
+    /// Parses `for i in iterable ...` or `for (init; condition; step) ...`.
+    fn parse_for(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::For)?.position();
+
+        if self.match_(TokenType::LeftParen)? {
+            return Ok(self.parse_for_clauses()?.at(position));
+        }
+
+        let ident = self.parse_ident()?;
+        self.expect(TokenType::In)?;
+        let iterable = self.expression()?;
+        let body = self.parse_loop_body()?;
+
+        Ok(Expr::for_in(ident, iterable, body).at(position))
     }
 
-    fn parse_if(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::If)?;
+    /// Parses the clauses of a C-style for loop and its body, the `(` is
+    /// already consumed. Every clause can be left out.
+    fn parse_for_clauses(&mut self) -> ParseResult<'a, Expr> {
+        let init = match self.peek_type()? {
+            TokenType::Semicolon => {
+                self.consume()?;
+                None
+            }
+            TokenType::Let | TokenType::Var | TokenType::Val => Some(self.parse_let()?),
+            _ => Some(self.parse_expr_statement()?),
+        };
+
+        let condition = if self.match_(TokenType::Semicolon)? {
+            None
+        } else {
+            let condition = self.expression()?;
+            self.expect(TokenType::Semicolon)?;
+            Some(condition)
+        };
+
+        let step = if self.check(TokenType::RightParen)? {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(TokenType::RightParen)?;
+
+        let body = self.parse_loop_body()?;
+
+        Ok(Expr::for_(init, condition, step, body))
+    }
+
+    /// Parses `{ ... }` or `do ... end`.
+    fn parse_loop_body(&mut self) -> ParseResult<'a, Expr> {
+        if self.check(TokenType::Do)? {
+            let position = *self.consume()?.position();
+            Ok(Expr::block(self.keyword_block()?).at(position))
+        } else {
+            self.parse_block()
+        }
+    }
+
+    fn parse_if(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::If)?.position();
 
         let cond = self.expression()?;
 
+        if self.match_(TokenType::Do)? {
+            return Ok(self.parse_if_do(cond)?.at(position));
+        }
+
         // Then branch.
         let then = self.block()?;
 
@@ -82,23 +339,126 @@ impl<'a> Parser<'a> {
             None
         };
 
+        Ok(Expr::if_else(cond, then, else_clause).at(position))
+    }
+
+    /// Parses the `if cond do ... else ... end` form, the `do` is already consumed.
+    fn parse_if_do(&mut self, cond: Expr) -> ParseResult<'a, Expr> {
+        // Then branch.
+        let then = self.block_until(&[TokenType::Else, TokenType::End])?;
+
+        // Else branch.
+        let else_clause = if self.match_(TokenType::Else)? {
+            Some(self.block_until(&[TokenType::End])?)
+        } else {
+            None
+        };
+
+        self.expect(TokenType::End)?;
+
         Ok(Expr::if_else(cond, then, else_clause))
     }
 
-    fn parse_print(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::Print)?;
+    /// Parses `match value` followed by its arms up to `end`. An arm is a
+    /// pattern, an optional `if` guard, `=>` and the body.
+    pub fn parse_match(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::Match)?.position();
+
+        let expr = self.expression()?;
+
+        let mut arms = vec![];
+        while !self.check(TokenType::End)? && !self.is_eof()? {
+            let pattern = self.parse_pattern()?;
+            let guard = if self.match_(TokenType::If)? {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            self.expect(TokenType::FatArrow)?;
+
+            let body = if self.check(TokenType::Do)? {
+                self.parse_loop_body()?
+            } else {
+                self.parse_top_level_expr()?
+            };
+            arms.push(MatchArm::new(pattern, guard, body));
+        }
+        self.expect(TokenType::End)?;
+
+        Ok(Expr::match_(expr, arms).at(position))
+    }
+
+    fn parse_pattern(&mut self) -> ParseResult<'a, Pattern> {
+        let token = self.consume()?;
+        let pattern = match token.token_type() {
+            TokenType::Underscore => Pattern::Wildcard,
+            TokenType::Number => {
+                Pattern::Literal(LiteralExpr::Number(token.source().parse::<f64>().unwrap()))
+            }
+            TokenType::Minus => {
+                let number = self.expect(TokenType::Number)?;
+                Pattern::Literal(LiteralExpr::Number(
+                    -number.source().parse::<f64>().unwrap(),
+                ))
+            }
+            TokenType::String => {
+                Pattern::Literal(LiteralExpr::String(expr_parser::unescape(token.source())))
+            }
+            TokenType::True => Pattern::Literal(LiteralExpr::True),
+            TokenType::False => Pattern::Literal(LiteralExpr::False),
+            TokenType::Nil => Pattern::Literal(LiteralExpr::Nil),
+            TokenType::LeftBracket => Pattern::List(self.parse_patterns(TokenType::RightBracket)?),
+            TokenType::Identifier if self.match_(TokenType::Dot)? => {
+                let variant = self.parse_ident()?;
+                let fields = if self.match_(TokenType::LeftParen)? {
+                    self.parse_patterns(TokenType::RightParen)?
+                } else {
+                    vec![]
+                };
+                Pattern::Variant {
+                    enum_: token.source().to_string(),
+                    variant,
+                    fields,
+                }
+            }
+            TokenType::Identifier => Pattern::Binding(token.source().to_string()),
+            _ => return Err(ParserError::Unexpected(token)),
+        };
+
+        Ok(pattern)
+    }
+
+    /// Parses comma-separated patterns up to `close`, the opening bracket is
+    /// already consumed.
+    fn parse_patterns(&mut self, close: TokenType) -> ParseResult<'a, Vec<Pattern>> {
+        let mut patterns = vec![];
+        while !self.check(close.clone())? && !self.is_eof()? {
+            patterns.push(self.parse_pattern()?);
+
+            if !self.match_(TokenType::Comma)? {
+                break;
+            }
+        }
+        self.expect(close)?;
+
+        Ok(patterns)
+    }
+
+    fn parse_print(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::Print)?.position();
         let expr = self.parse_expr_statement()?;
-        Ok(Expr::print(expr))
+        Ok(Expr::print(expr).at(position))
     }
 
-    fn parse_block(&mut self) -> ParseResult<Expr> {
-        Ok(Expr::block(self.block()?))
+    fn parse_block(&mut self) -> ParseResult<'a, Expr> {
+        let position = self.position()?;
+        Ok(Expr::block(self.block()?).at(position))
     }
 
-    fn parse_return(&mut self) -> ParseResult<Expr> {
-        self.expect(TokenType::Return)?;
+    fn parse_return(&mut self) -> ParseResult<'a, Expr> {
+        let position = *self.expect(TokenType::Return)?.position();
 
-        let expr = if self.match_(TokenType::Semicolon)? {
+        let expr = if self.match_(TokenType::Semicolon)? || self.is_block_end()? {
             // return;
             None
         } else {
@@ -106,29 +466,44 @@ impl<'a> Parser<'a> {
             Some(self.parse_top_level_expr()?)
         };
 
-        Ok(Expr::return_(expr))
+        Ok(Expr::return_(expr).at(position))
+    }
+
+    fn parse_loop_jump(&mut self) -> ParseResult<'a, Expr> {
+        let token = self.consume()?;
+        let expr = match token.token_type() {
+            TokenType::Break => Expr::break_(),
+            TokenType::Continue => Expr::continue_(),
+            _ => return Err(ParserError::Unexpected(token)),
+        };
+        self.match_(TokenType::Semicolon)?;
+
+        Ok(expr.at(*token.position()))
     }
 
-    pub fn parse_expr_statement(&mut self) -> ParseResult<Expr> {
+    pub fn parse_expr_statement(&mut self) -> ParseResult<'a, Expr> {
         let expr = self.expression()?;
         self.match_(TokenType::Semicolon)?;
         Ok(expr)
     }
 
-    pub fn expression(&mut self) -> ParseResult<Expr> {
+    pub fn expression(&mut self) -> ParseResult<'a, Expr> {
         expr_parser::parse(self)
     }
 
-    pub fn parse_ident(&mut self) -> ParseResult<Identifier> {
+    pub fn parse_ident(&mut self) -> ParseResult<'a, Identifier> {
         Ok(self.expect(TokenType::Identifier)?.source().to_string())
     }
 
-    pub fn parse_args(&mut self) -> ParseResult<Vec<Identifier>> {
+    /// Parses the parameters of a function, each optionally annotated with
+    /// a type.
+    pub fn parse_params(&mut self) -> ParseResult<'a, Vec<Param>> {
         self.expect(TokenType::LeftParen)?;
 
-        let mut args = vec![];
+        let mut params = vec![];
         while !self.check(TokenType::RightParen)? && !self.check(TokenType::EOF)? {
-            args.push(self.parse_ident()?);
+            let ident = self.parse_ident()?;
+            params.push(Param::new(ident, self.parse_annotation()?));
 
             if !self.match_(TokenType::Comma)? {
                 break;
@@ -137,46 +512,209 @@ impl<'a> Parser<'a> {
 
         self.expect(TokenType::RightParen)?;
 
-        Ok(args)
+        Ok(params)
+    }
+
+    /// Parses the `: type` following a name, if there is one.
+    fn parse_annotation(&mut self) -> ParseResult<'a, Option<TypeExpr>> {
+        if !self.match_(TokenType::Colon)? {
+            return Ok(None);
+        }
+
+        Ok(Some(self.parse_type()?))
     }
 
-    fn block(&mut self) -> ParseResult<BlockDecl> {
-        self.expect(TokenType::LeftBrace);
+    fn parse_type(&mut self) -> ParseResult<'a, TypeExpr> {
+        let start = self.position()?;
+
+        // nil is a keyword, but also the name of its type.
+        if self.match_(TokenType::Nil)? {
+            return Ok(TypeExpr::named("nil".to_string()).at(start));
+        }
+
+        let ident = self.parse_ident()?;
+        if !self.match_(TokenType::LessThan)? {
+            return Ok(TypeExpr::named(ident).at(start));
+        }
+
+        let mut args = vec![];
+        while !self.check(TokenType::GreaterThan)? && !self.is_eof()? {
+            args.push(self.parse_type()?);
 
+            if !self.match_(TokenType::Comma)? {
+                break;
+            }
+        }
+        let end = self.expect(TokenType::GreaterThan)?;
+
+        Ok(TypeExpr::generic(ident, args).at(start.to(end.position())))
+    }
+
+    /// Parses the type parameters of a generic declaration, `<T, U: Shape>`,
+    /// if there are any.
+    fn parse_type_params(&mut self) -> ParseResult<'a, Vec<TypeParam>> {
+        let mut params = vec![];
+        if !self.match_(TokenType::LessThan)? {
+            return Ok(params);
+        }
+
+        while !self.check(TokenType::GreaterThan)? && !self.is_eof()? {
+            let ident = self.parse_ident()?;
+            let bound = if self.match_(TokenType::Colon)? {
+                Some(self.parse_ident()?)
+            } else {
+                None
+            };
+            params.push(TypeParam::new(ident, bound));
+
+            if !self.match_(TokenType::Comma)? {
+                break;
+            }
+        }
+        self.expect(TokenType::GreaterThan)?;
+
+        Ok(params)
+    }
+
+    fn block(&mut self) -> ParseResult<'a, BlockDecl> {
+        self.expect(TokenType::LeftBrace)?;
+        let exprs = self.block_until(&[TokenType::RightBrace])?;
+        self.expect(TokenType::RightBrace)?;
+
+        Ok(exprs)
+    }
+
+    /// Parses a block that is terminated by the `end` keyword.
+    fn keyword_block(&mut self) -> ParseResult<'a, BlockDecl> {
+        let exprs = self.block_until(&[TokenType::End])?;
+        self.expect(TokenType::End)?;
+
+        Ok(exprs)
+    }
+
+    /// Parses expressions until one of the terminators is found, the
+    /// terminator itself is not consumed. An expression that fails to parse
+    /// is recorded and skipped.
+    fn block_until(&mut self, terminators: &[TokenType]) -> ParseResult<'a, BlockDecl> {
         let mut exprs = vec![];
-        while !self.match_(TokenType::RightBrace)? {
-            exprs.push(self.parse_top_level_expr()?);
+        while !terminators.contains(self.peek_type()?) && !self.is_eof()? {
+            match self.parse_top_level_expr() {
+                Ok(expr) => exprs.push(expr),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize()?;
+                }
+            }
         }
 
         Ok(exprs)
     }
 
-    pub fn expect(&mut self, expect: TokenType) -> ParseResult<Token<'a>> {
+    /// Skips tokens until the start of the next expression: after a `;`, or
+    /// before a keyword that starts an expression or a token that closes a
+    /// block. The offending token is always skipped unless it starts an
+    /// expression, so parsing makes progress.
+    fn synchronize(&mut self) -> ParseResult<'a, ()> {
+        if !self.is_eof()?
+            && !self.is_expr_start()?
+            && self.consume()?.token_type() == &TokenType::Semicolon
+        {
+            return Ok(());
+        }
+
+        while !self.is_eof()? && !self.is_expr_start()? {
+            match self.peek_type()? {
+                TokenType::RightBrace | TokenType::End | TokenType::Else => return Ok(()),
+                TokenType::Semicolon => {
+                    self.consume()?;
+                    return Ok(());
+                }
+                _ => {
+                    self.consume()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_expr_start(&self) -> ParseResult<'a, bool> {
+        Ok(matches!(
+            self.peek_type()?,
+            TokenType::Let
+                | TokenType::Val
+                | TokenType::Var
+                | TokenType::Fun
+                | TokenType::Def
+                | TokenType::Class
+                | TokenType::Trait
+                | TokenType::Impl
+                | TokenType::Enum
+                | TokenType::Match
+                | TokenType::If
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue
+        ))
+    }
+
+    /// Whether the `{` up next opens a map literal rather than a block, a
+    /// block never has a `:` after its first token.
+    pub fn is_map_start(&self) -> ParseResult<'a, bool> {
+        Ok(self.check(TokenType::LeftBrace)?
+            && self.peek_nth(2).map(Token::token_type) == Some(&TokenType::Colon))
+    }
+
+    fn is_block_end(&self) -> ParseResult<'a, bool> {
+        Ok(matches!(
+            self.peek_type()?,
+            TokenType::End | TokenType::Else | TokenType::RightBrace
+        ))
+    }
+
+    pub fn expect(&mut self, expect: TokenType) -> ParseResult<'a, Token<'a>> {
         if self.check(expect.clone())? {
             // TODO: Clone
-            return Ok(self.consume()?);
+            return self.consume();
         }
 
-        Err(ParserError::Expected(
-            expect.clone(),                         // TODO: Clone
-            self.peek_type()?.clone(),              // TODO: Clone
-            self.peek()?.position().line().clone(), // TODO: Clone
-        ))
+        Err(ParserError::Expected(expect, self.peek()?.clone()))
     }
 
-    pub fn consume(&mut self) -> ParseResult<Token<'a>> {
-        self.tokens.pop().ok_or(ParserError::UnexpectedEOF)
+    pub fn consume(&mut self) -> ParseResult<'a, Token<'a>> {
+        let token = self.tokens.pop().ok_or(ParserError::UnexpectedEOF)?;
+        self.line = *token.position().line();
+        Ok(token)
     }
 
-    pub fn peek(&self) -> ParseResult<&Token<'a>> {
+    pub fn peek(&self) -> ParseResult<'a, &Token<'a>> {
         self.tokens.last().ok_or(ParserError::UnexpectedEOF)
     }
 
-    pub fn peek_type(&self) -> ParseResult<&TokenType> {
+    /// The token `n` tokens past the next one, `peek_nth(0)` is the next
+    /// token itself.
+    pub fn peek_nth(&self, n: usize) -> Option<&Token<'a>> {
+        self.tokens.iter().rev().nth(n)
+    }
+
+    /// The position of the next token.
+    pub fn position(&self) -> ParseResult<'a, Position> {
+        Ok(*self.peek()?.position())
+    }
+
+    /// Whether the next token is on a later line than the last consumed one.
+    pub fn starts_line(&self) -> ParseResult<'a, bool> {
+        Ok(*self.peek()?.position().line() > self.line)
+    }
+
+    pub fn peek_type(&self) -> ParseResult<'a, &TokenType> {
         Ok(self.peek()?.token_type())
     }
 
-    pub fn match_(&mut self, token_type: TokenType) -> ParseResult<bool> {
+    pub fn match_(&mut self, token_type: TokenType) -> ParseResult<'a, bool> {
         if !self.check(token_type)? {
             return Ok(false);
         }
@@ -185,12 +723,12 @@ impl<'a> Parser<'a> {
         Ok(true)
     }
 
-    pub fn check(&self, token_type: TokenType) -> ParseResult<bool> {
+    pub fn check(&self, token_type: TokenType) -> ParseResult<'a, bool> {
         Ok(self.peek_type()? == &token_type)
     }
 
-    pub fn is_eof(&self) -> ParseResult<bool> {
-        Ok(self.check(TokenType::EOF)?)
+    pub fn is_eof(&self) -> ParseResult<'a, bool> {
+        self.check(TokenType::EOF)
     }
 }
 
@@ -211,18 +749,29 @@ mod tests {
     fn parse_assign_let() {
         let expect = vec![Expr::let_assign(
             "x".to_string(),
-            Expr::Literal(LiteralExpr::Number(5.0)),
+            Expr::literal(LiteralExpr::Number(5.0)),
         )];
 
         let source = "let x = 5;";
         run_test(expect, source);
     }
 
+    #[test]
+    fn parse_val_var() {
+        let expect = vec![
+            Expr::val_assign("x".to_string(), Expr::literal(LiteralExpr::Number(10.0))),
+            Expr::let_assign("y".to_string(), Expr::literal(LiteralExpr::Number(3.0))),
+        ];
+
+        let source = "val x = 10 var y = 3";
+        run_test(expect, source);
+    }
+
     #[test]
     fn parse_set_let() {
         let expect = vec![Expr::let_set(
             "x".to_string(),
-            Expr::Literal(LiteralExpr::Number(5.0)),
+            Expr::literal(LiteralExpr::Number(5.0)),
         )];
 
         let source = "x = 5;";
@@ -232,7 +781,7 @@ mod tests {
     #[test]
     fn parse_get_let() {
         let expect = vec![
-            Expr::let_assign("x".to_string(), Expr::Literal(LiteralExpr::Number(5.0))),
+            Expr::let_assign("x".to_string(), Expr::literal(LiteralExpr::Number(5.0))),
             Expr::let_assign("y".to_string(), Expr::let_get("x".to_string())),
         ];
 
@@ -246,7 +795,7 @@ mod tests {
             Expr::binary(
                 Expr::let_get("x".to_string()),
                 BinaryOperator::LessThan,
-                Expr::Literal(LiteralExpr::Number(5_f64)),
+                Expr::literal(LiteralExpr::Number(5_f64)),
             ),
             Expr::block(vec![Expr::print(Expr::let_get("x".to_string()))]),
         )];
@@ -259,10 +808,30 @@ mod tests {
         run_test(expect, source);
     }
 
+    #[test]
+    fn parse_logical_precedence() {
+        let expect = vec![Expr::binary(
+            Expr::let_get("a".to_string()),
+            BinaryOperator::Or,
+            Expr::binary(
+                Expr::let_get("b".to_string()),
+                BinaryOperator::And,
+                Expr::binary(
+                    Expr::let_get("c".to_string()),
+                    BinaryOperator::Equal,
+                    Expr::literal(LiteralExpr::Nil),
+                ),
+            ),
+        )];
+
+        let source = "a or b && c == nil";
+        run_test(expect, source);
+    }
+
     #[test]
     fn parse_block() {
         let expect = vec![Expr::block(vec![
-            Expr::let_assign("x".to_string(), Expr::Literal(LiteralExpr::Number(5.0))),
+            Expr::let_assign("x".to_string(), Expr::literal(LiteralExpr::Number(5.0))),
             Expr::let_assign("y".to_string(), Expr::let_get("x".to_string())),
         ])];
 
@@ -278,9 +847,9 @@ mod tests {
     #[test]
     fn parse_grouping() {
         let expect = vec![Expr::grouping(Expr::binary(
-            Expr::Literal(LiteralExpr::Number(2.0)),
+            Expr::literal(LiteralExpr::Number(2.0)),
             BinaryOperator::Add,
-            Expr::Literal(LiteralExpr::Number(4.0)),
+            Expr::literal(LiteralExpr::Number(4.0)),
         ))];
 
         let source = r#"(2 + 4);"#;
@@ -293,9 +862,9 @@ mod tests {
             Expr::binary(
                 Expr::let_get("x".to_string()),
                 BinaryOperator::LessThan,
-                Expr::Literal(LiteralExpr::Number(5_f64)),
+                Expr::literal(LiteralExpr::Number(5_f64)),
             ),
-            vec![Expr::return_(Some(Expr::Literal(LiteralExpr::True)))],
+            vec![Expr::return_(Some(Expr::literal(LiteralExpr::True)))],
             None,
         )];
 
@@ -313,10 +882,10 @@ mod tests {
             Expr::binary(
                 Expr::let_get("x".to_string()),
                 BinaryOperator::LessThan,
-                Expr::Literal(LiteralExpr::Number(5_f64)),
+                Expr::literal(LiteralExpr::Number(5_f64)),
             ),
-            vec![Expr::return_(Some(Expr::Literal(LiteralExpr::True)))],
-            Some(vec![Expr::return_(Some(Expr::Literal(LiteralExpr::False)))]),
+            vec![Expr::return_(Some(Expr::literal(LiteralExpr::True)))],
+            Some(vec![Expr::return_(Some(Expr::literal(LiteralExpr::False)))]),
         )];
 
         let source = r#"
@@ -340,19 +909,289 @@ mod tests {
 
     #[test]
     fn parse_return_value() {
-        let expect = vec![Expr::return_(Some(Expr::Literal(LiteralExpr::True)))];
+        let expect = vec![Expr::return_(Some(Expr::literal(LiteralExpr::True)))];
 
         let source = "return true;";
         run_test(expect, source);
     }
 
+    #[test]
+    fn parse_list() {
+        let expect = vec![Expr::list(vec![
+            Expr::literal(LiteralExpr::Number(1.0)),
+            Expr::list(vec![]),
+        ])];
+
+        let source = "[1, []]";
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_map() {
+        let expect = vec![
+            Expr::let_assign(
+                "m".to_string(),
+                Expr::map(vec![(
+                    Expr::literal(LiteralExpr::String("a".to_string())),
+                    Expr::map(vec![]),
+                )]),
+            ),
+            Expr::map(vec![(
+                Expr::literal(LiteralExpr::Number(1.0)),
+                Expr::literal(LiteralExpr::Nil),
+            )]),
+            Expr::block(vec![Expr::let_get("x".to_string())]),
+        ];
+
+        let source = r#"
+        var m = { "a": {} }
+        { 1: nil }
+        { x }
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_index() {
+        let expect = vec![
+            Expr::index(
+                Expr::call(Expr::let_get("f".to_string()), vec![]),
+                Expr::unary(
+                    UnaryOperator::Negate,
+                    Expr::literal(LiteralExpr::Number(1.0)),
+                ),
+            ),
+            Expr::set_index(
+                Expr::index(
+                    Expr::let_get("xs".to_string()),
+                    Expr::literal(LiteralExpr::Number(0.0)),
+                ),
+                Expr::literal(LiteralExpr::Number(1.0)),
+                Expr::literal(LiteralExpr::Nil),
+            ),
+        ];
+
+        let source = "f()[-1]; xs[0][1] = nil";
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_class() {
+        let expect = vec![
+            Expr::class(
+                "Rectangle".to_string(),
+                ClassDecl::new(
+                    vec![
+                        FieldDecl::new("width".to_string(), true),
+                        FieldDecl::new("height".to_string(), false),
+                    ],
+                    vec![],
+                ),
+            ),
+            Expr::class(
+                "Circle".to_string(),
+                ClassDecl::new(
+                    vec![FieldDecl::new("radius".to_string(), false)],
+                    vec![(
+                        "diameter".to_string(),
+                        FunDecl::new(vec![], None, vec![Expr::self_value()]),
+                    )],
+                ),
+            ),
+        ];
+
+        let source = r#"
+        class Rectangle (var width, val height)
+        class Circle
+            radius
+            def diameter()
+                self
+            end
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_receiver_method() {
+        let expect = vec![Expr::method(
+            "Rectangle".to_string(),
+            "new".to_string(),
+            FunDecl::new(vec![Param::new("width".to_string(), None)], None, vec![]),
+        )];
+
+        let source = "def (Rectangle) new(width) end";
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_trait_and_impl() {
+        let expect = vec![
+            Expr::trait_(
+                "Shape".to_string(),
+                vec![
+                    FunSignature::new("name".to_string(), vec![], None),
+                    FunSignature::new(
+                        "scale".to_string(),
+                        vec![Param::new("by".to_string(), None)],
+                        None,
+                    ),
+                ],
+            ),
+            Expr::impl_(
+                "Shape".to_string(),
+                "Circle".to_string(),
+                vec![(
+                    "name".to_string(),
+                    FunDecl::new(
+                        vec![],
+                        None,
+                        vec![Expr::return_(Some(Expr::literal(LiteralExpr::String(
+                            "circle".to_string(),
+                        ))))],
+                    ),
+                )],
+            ),
+        ];
+
+        let source = r#"
+        trait Shape
+            def name()
+            fun scale(by);
+        end
+        impl Shape for Circle
+            def name()
+                return "circle"
+            end
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_enum() {
+        let expect = vec![Expr::enum_(
+            "Shape".to_string(),
+            vec![
+                VariantDecl::new("Circle".to_string(), vec!["radius".to_string()]),
+                VariantDecl::new(
+                    "Rect".to_string(),
+                    vec!["width".to_string(), "height".to_string()],
+                ),
+                VariantDecl::new("Empty".to_string(), vec![]),
+            ],
+        )];
+
+        let source = r#"
+        enum Shape
+            Circle(radius),
+            Rect(width, height),
+            Empty,
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_match() {
+        let expect = vec![Expr::match_(
+            Expr::let_get("shape".to_string()),
+            vec![
+                MatchArm::new(
+                    Pattern::Variant {
+                        enum_: "Shape".to_string(),
+                        variant: "Circle".to_string(),
+                        fields: vec![Pattern::Binding("r".to_string())],
+                    },
+                    Some(Expr::binary(
+                        Expr::let_get("r".to_string()),
+                        BinaryOperator::GreaterThan,
+                        Expr::literal(LiteralExpr::Number(0.0)),
+                    )),
+                    Expr::let_get("r".to_string()),
+                ),
+                MatchArm::new(
+                    Pattern::List(vec![
+                        Pattern::Literal(LiteralExpr::Number(-1.0)),
+                        Pattern::Wildcard,
+                    ]),
+                    None,
+                    Expr::literal(LiteralExpr::String("list".to_string())),
+                ),
+                MatchArm::new(
+                    Pattern::Wildcard,
+                    None,
+                    Expr::print(Expr::literal(LiteralExpr::Nil)),
+                ),
+            ],
+        )];
+
+        let source = r#"
+        match shape
+            Shape.Circle(r) if r > 0 => r
+            [-1, _] => "list"
+            _ => print nil
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_interpolation() {
+        let string = |s: &str| Expr::literal(LiteralExpr::String(s.to_string()));
+        let expect = vec![Expr::print(Expr::grouping(Expr::interpolation(vec![
+            string("Hello, "),
+            Expr::let_get("name".to_string()),
+            string(" is "),
+            Expr::binary(
+                Expr::literal(LiteralExpr::Number(1.0)),
+                BinaryOperator::Add,
+                Expr::literal(LiteralExpr::Number(2.0)),
+            ),
+            string(" $"),
+        ])))];
+
+        let source = r#"print("Hello, $name is ${1 + 2} \$")"#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_property() {
+        let expect = vec![
+            Expr::call(
+                Expr::get_property(Expr::let_get("rect".to_string()), "area".to_string()),
+                vec![],
+            ),
+            Expr::set_property(
+                Expr::get_property(Expr::self_value(), "origin".to_string()),
+                "x".to_string(),
+                Expr::literal(LiteralExpr::Number(1.0)),
+            ),
+        ];
+
+        let source = "rect.area(); self.origin.x = 1";
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_break_continue() {
+        let expect = vec![Expr::while_(
+            Expr::literal(LiteralExpr::True),
+            Expr::block(vec![Expr::continue_(), Expr::break_()]),
+        )];
+
+        let source = "while true do continue; break end";
+        run_test(expect, source);
+    }
+
     #[test]
     fn parse_def() {
         let expect = vec![Expr::fun(
             "foo".to_string(),
             FunDecl::new(
                 vec![],
-                vec![Expr::return_(Some(Expr::Literal(LiteralExpr::True)))],
+                None,
+                vec![Expr::return_(Some(Expr::literal(LiteralExpr::True)))],
             ),
         )];
 
@@ -363,4 +1202,239 @@ mod tests {
         "#;
         run_test(expect, source);
     }
+
+    #[test]
+    fn parse_def_end() {
+        let expect = vec![Expr::fun(
+            "foo".to_string(),
+            FunDecl::new(
+                vec![Param::new("a".to_string(), None)],
+                None,
+                vec![Expr::return_(Some(Expr::let_get("a".to_string())))],
+            ),
+        )];
+
+        let source = r#"
+        def foo(a)
+            return a
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_annotations() {
+        let number = || Some(TypeExpr::named("int".to_string()));
+        let expect = vec![
+            Expr::fun(
+                "double".to_string(),
+                FunDecl::new(
+                    vec![Param::new("x".to_string(), number())],
+                    number(),
+                    vec![],
+                ),
+            ),
+            Expr::binding(
+                "y".to_string(),
+                number(),
+                Expr::literal(LiteralExpr::Number(2.0)),
+                false,
+            ),
+            Expr::class(
+                "Rectangle".to_string(),
+                ClassDecl::new(
+                    vec![FieldDecl::typed("width".to_string(), true, number())],
+                    vec![],
+                ),
+            ),
+        ];
+
+        let source = r#"
+        def double(x: int): int end
+        val y: int = 2
+        class Rectangle (var width: int)
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_generics() {
+        let t = || Some(TypeExpr::named("T".to_string()));
+        let expect = vec![
+            Expr::fun(
+                "first".to_string(),
+                FunDecl::generic(
+                    vec![TypeParam::new("T".to_string(), Some("Shape".to_string()))],
+                    vec![Param::new(
+                        "items".to_string(),
+                        Some(TypeExpr::generic(
+                            "List".to_string(),
+                            vec![TypeExpr::named("T".to_string())],
+                        )),
+                    )],
+                    t(),
+                    vec![],
+                ),
+            ),
+            Expr::class(
+                "Box".to_string(),
+                ClassDecl::generic(
+                    vec![TypeParam::new("T".to_string(), None)],
+                    vec![FieldDecl::typed("value".to_string(), false, t())],
+                    vec![],
+                ),
+            ),
+            Expr::generic_enum(
+                "Option".to_string(),
+                vec![TypeParam::new("T".to_string(), None)],
+                vec![
+                    VariantDecl::typed(
+                        "Some".to_string(),
+                        vec![Param::new("value".to_string(), t())],
+                    ),
+                    VariantDecl::new("None".to_string(), vec![]),
+                ],
+            ),
+        ];
+
+        let source = r#"
+        def first<T: Shape>(items: List<T>): T end
+        class Box<T> (val value: T)
+        enum Option<T> Some(value: T), None end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_while_do() {
+        let expect = vec![Expr::while_(
+            Expr::binary(
+                Expr::let_get("x".to_string()),
+                BinaryOperator::LessThan,
+                Expr::literal(LiteralExpr::Number(5_f64)),
+            ),
+            Expr::block(vec![Expr::print(Expr::let_get("x".to_string()))]),
+        )];
+
+        let source = r#"
+        while x < 5 do
+            print x
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_for_in() {
+        let expect = vec![Expr::for_in(
+            "i".to_string(),
+            Expr::binary(
+                Expr::literal(LiteralExpr::Number(0.0)),
+                BinaryOperator::Range,
+                Expr::binary(
+                    Expr::let_get("n".to_string()),
+                    BinaryOperator::Add,
+                    Expr::literal(LiteralExpr::Number(1.0)),
+                ),
+            ),
+            Expr::block(vec![Expr::print(Expr::let_get("i".to_string()))]),
+        )];
+
+        let source = r#"
+        for i in 0..n + 1 do
+            print i
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_for_clauses() {
+        let expect = vec![
+            Expr::for_(
+                Some(Expr::let_assign(
+                    "i".to_string(),
+                    Expr::literal(LiteralExpr::Number(0.0)),
+                )),
+                Some(Expr::binary(
+                    Expr::let_get("i".to_string()),
+                    BinaryOperator::LessThan,
+                    Expr::literal(LiteralExpr::Number(3.0)),
+                )),
+                Some(Expr::let_set(
+                    "i".to_string(),
+                    Expr::binary(
+                        Expr::let_get("i".to_string()),
+                        BinaryOperator::Add,
+                        Expr::literal(LiteralExpr::Number(1.0)),
+                    ),
+                )),
+                Expr::block(vec![]),
+            ),
+            Expr::for_(None, None, None, Expr::block(vec![])),
+        ];
+
+        let source = r#"
+        for (var i = 0; i < 3; i = i + 1) {}
+        for (;;) do end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_if_do_else() {
+        let expect = vec![Expr::if_else(
+            Expr::binary(
+                Expr::let_get("x".to_string()),
+                BinaryOperator::LessThan,
+                Expr::literal(LiteralExpr::Number(5_f64)),
+            ),
+            vec![Expr::return_(None)],
+            Some(vec![Expr::return_(Some(Expr::literal(LiteralExpr::Nil)))]),
+        )];
+
+        let source = r#"
+        if x < 5 do
+            return
+        else
+            return nil
+        end
+        "#;
+        run_test(expect, source);
+    }
+
+    #[test]
+    fn parse_recovers_after_errors() {
+        let source = r#"
+        val a = (1 +
+        print a
+        def f()
+            val b = )
+            return b
+        end
+        val c = 3 }
+        "#;
+        let mut tokens = lex(source).unwrap();
+        let errors = parse(&mut tokens).unwrap_err();
+
+        let found: Vec<(String, usize)> = errors
+            .iter()
+            .map(|error| {
+                let token = error.token().unwrap();
+                (token.source().to_string(), *token.position().line())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("print".to_string(), 3),
+                (")".to_string(), 5),
+                ("}".to_string(), 8),
+            ],
+            found
+        );
+        assert_eq!(
+            "Expected expression but found 'print'.",
+            errors[0].to_string()
+        );
+    }
 }
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+pub const HELP: &str = "\
+:help           Show this message
+:load <file>    Run a script in this session
+:reset          Forget every global
+:globals        List the globals and their values
+:disasm <code>  Show the bytecode of the code without running it
+:time <code>    Run the code and show how long it took
+:quit           End the session";
+
+/// A REPL meta-command, entered as a line starting with `:`.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Load(String),
+    Reset,
+    Globals,
+    Disasm(String),
+    Time(String),
+    Quit,
+}
+
+impl Command {
+    /// Parses a line that starts with `:`.
+    pub fn parse(line: &str) -> Result<Command, CommandError> {
+        let line = line.trim().trim_start_matches(':');
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        let argument = |usage: &'static str| {
+            if argument.is_empty() {
+                Err(CommandError::MissingArgument(usage))
+            } else {
+                Ok(argument.to_string())
+            }
+        };
+
+        Ok(match name {
+            "help" | "h" => Command::Help,
+            "load" | "l" => Command::Load(argument(":load <file>")?),
+            "reset" => Command::Reset,
+            "globals" | "g" => Command::Globals,
+            "disasm" | "d" => Command::Disasm(argument(":disasm <code>")?),
+            "time" | "t" => Command::Time(argument(":time <code>")?),
+            "quit" | "q" | "exit" => Command::Quit,
+            other => return Err(CommandError::Unknown(other.to_string())),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    MissingArgument(&'static str),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(name) => {
+                write!(f, "Unknown command ':{}', try ':help'.", name)
+            }
+            CommandError::MissingArgument(usage) => write!(f, "Usage: {}", usage),
+        }
+    }
+}
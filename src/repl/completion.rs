@@ -0,0 +1,114 @@
+use crate::lexer::token::KEYWORDS;
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+/// Completes the identifier that ends at byte `pos` of `line` from the
+/// keywords and `names`. Returns where the identifier starts and every
+/// candidate that extends it, sorted.
+pub fn complete<'a>(
+    line: &str,
+    pos: usize,
+    names: impl IntoIterator<Item = &'a str>,
+) -> (usize, Vec<String>) {
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric())
+        .last()
+        .map_or(pos, |(i, _)| i);
+
+    let prefix = &line[start..pos];
+    if prefix.is_empty() {
+        return (pos, vec![]);
+    }
+
+    let mut candidates: Vec<String> = KEYWORDS
+        .iter()
+        .map(|(keyword, _)| *keyword)
+        .chain(names)
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| name.to_string())
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+
+    (start, candidates)
+}
+
+/// Hooks completion into the line editor. The REPL refreshes `names` with
+/// the globals after every input.
+#[derive(Default)]
+pub struct ReplHelper {
+    names: Vec<String>,
+}
+
+impl ReplHelper {
+    pub fn set_names(&mut self, names: Vec<String>) {
+        self.names = names;
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, self.names.iter().map(String::as_str)))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_keywords() {
+        assert_eq!((0, vec!["print".to_string()]), complete("pri", 3, vec![]));
+        assert_eq!(
+            (0, vec!["val".to_string(), "var".to_string()]),
+            complete("va", 2, vec![])
+        );
+    }
+
+    #[test]
+    fn complete_names() {
+        let names = vec!["fibonacci", "factor", "while2"];
+
+        assert_eq!(
+            (6, vec!["fibonacci".to_string()]),
+            complete("print(fib", 9, names.clone())
+        );
+        assert_eq!(
+            (0, vec!["while".to_string(), "while2".to_string()]),
+            complete("while", 5, names.clone())
+        );
+        // Only the part before the cursor is completed.
+        assert_eq!(
+            (4, vec!["factor".to_string(), "false".to_string()]),
+            complete("1 + fa + 2", 6, names.clone())
+        );
+    }
+
+    #[test]
+    fn complete_nothing() {
+        assert_eq!((4, vec![]), complete("1 + ", 4, vec!["a"]));
+        assert_eq!((0, vec![]), complete("zzz", 3, vec!["a"]));
+    }
+}
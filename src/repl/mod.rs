@@ -0,0 +1,393 @@
+use crate::compiler::value::Value;
+use crate::compiler::{compile, compile_repl, disassemble};
+use crate::lexer::error::SyntaxError;
+use crate::lexer::lex;
+use crate::lexer::token::TokenType;
+use crate::parser::ast::Identifier;
+use crate::parser::parse;
+use crate::repl::command::{Command, HELP};
+use crate::repl::completion::{complete, ReplHelper};
+use crate::report;
+use crate::vm::error::{InterpretError, InterpretResult};
+use crate::vm::vm::VM;
+use rustyline::error::ReadlineError;
+use rustyline::history::FileHistory;
+use rustyline::Editor;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, Stdout, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+pub mod command;
+pub mod completion;
+
+/// A REPL session. Globals and functions defined by one input stay available
+/// to the next.
+pub struct Repl<W: Write> {
+    vm: VM<W>,
+    immutable_globals: HashSet<Identifier>,
+}
+
+impl Repl<Stdout> {
+    pub fn new() -> Self {
+        Repl::with_stdout(io::stdout())
+    }
+}
+
+impl<W: Write> Repl<W> {
+    pub fn with_stdout(stdout: W) -> Self {
+        Repl {
+            vm: VM::with_stdout(stdout),
+            immutable_globals: HashSet::new(),
+        }
+    }
+
+    /// Runs one complete input. The value of a trailing bare expression is
+    /// echoed unless it is nil.
+    pub fn eval(&mut self, source: &str) -> InterpretResult<()> {
+        let fun =
+            compile_repl(source, &mut self.immutable_globals).map_err(InterpretError::Compile)?;
+        let value = self.vm.interpret(fun).map_err(InterpretError::Runtime)?;
+
+        if !matches!(value, Value::Nil) {
+            self.print(&value.to_string());
+        }
+        Ok(())
+    }
+
+    /// Runs a meta-command, returns `false` once the session should end.
+    pub fn command(&mut self, command: Command) -> bool {
+        match command {
+            Command::Help => self.print(HELP),
+            Command::Load(path) => match fs::read_to_string(&path) {
+                Ok(source) => {
+                    if let Err(error) = self.eval(&source) {
+                        report(&error, &source, &path);
+                    }
+                }
+                Err(error) => eprintln!("Could not read '{}': {}", path, error),
+            },
+            Command::Reset => {
+                self.vm.globals_mut().clear();
+                self.immutable_globals.clear();
+            }
+            Command::Globals => {
+                let mut globals: Vec<String> = self
+                    .vm
+                    .globals()
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                globals.sort();
+
+                for global in globals {
+                    self.print(&global);
+                }
+            }
+            Command::Disasm(code) => match compile(&code) {
+                Ok(fun) => self.print(disassemble(&fun).trim_end()),
+                Err(diagnostics) => report(&InterpretError::Compile(diagnostics), &code, "repl"),
+            },
+            Command::Time(code) => {
+                let start = Instant::now();
+                let result = self.eval(&code);
+                let elapsed = start.elapsed();
+
+                if let Err(error) = result {
+                    report(&error, &code, "repl");
+                }
+                self.print(&format!("Took {:?}", elapsed));
+            }
+            Command::Quit => return false,
+        }
+
+        true
+    }
+
+    /// Completes the identifier that ends at byte `pos` of `line` from the
+    /// keywords, the built-in functions and the globals defined so far.
+    pub fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let natives = self.vm.natives().keys().copied();
+        complete(
+            line,
+            pos,
+            natives.chain(self.vm.globals().keys().map(String::as_str)),
+        )
+    }
+
+    pub fn global_names(&self) -> Vec<String> {
+        let natives = self.vm.natives().keys().map(|name| name.to_string());
+        natives.chain(self.vm.globals().keys().cloned()).collect()
+    }
+
+    fn print(&mut self, text: &str) {
+        let _ = writeln!(self.vm.stdout_mut(), "{}", text);
+    }
+}
+
+impl Default for Repl<Stdout> {
+    fn default() -> Self {
+        Repl::new()
+    }
+}
+
+/// Whether the input stops in the middle of an expression, a block or a
+/// string, so another line should be read before running it.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(errors) => {
+            return errors.iter().any(|error| {
+                matches!(
+                    error,
+                    SyntaxError::UnterminatedString(_) | SyntaxError::UnexpectedEOF(_)
+                )
+            })
+        }
+    };
+
+    match parse(&mut tokens) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(|error| match error.token() {
+            Some(token) => token.token_type() == &TokenType::EOF,
+            None => true,
+        }),
+    }
+}
+
+/// Reads inputs until stdin is closed or `:quit` is entered. Errors are
+/// printed and the session carries on.
+pub fn run() {
+    let mut editor: Editor<ReplHelper, FileHistory> = match Editor::new() {
+        Ok(editor) => editor,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            return;
+        }
+    };
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history yet on the first run.
+        let _ = editor.load_history(path);
+    }
+
+    let mut repl = Repl::new();
+    let mut input = String::new();
+    editor.set_helper(Some(ReplHelper::default()));
+
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_names(repl.global_names());
+        }
+
+        let prompt = if input.is_empty() { "> " } else { ".. " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // Ctrl-C drops the unfinished input.
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => {
+                println!();
+                break;
+            }
+            Err(error) => {
+                eprintln!("error: {}", error);
+                break;
+            }
+        };
+
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        if input.is_empty() && line.trim_start().starts_with(':') {
+            match Command::parse(&line) {
+                Ok(command) => {
+                    if !repl.command(command) {
+                        break;
+                    }
+                }
+                Err(error) => eprintln!("{}", error),
+            }
+            continue;
+        }
+
+        // A blank line runs an unfinished input anyway, to show its errors.
+        let blank = line.trim().is_empty();
+        input.push_str(&line);
+        input.push('\n');
+        if !blank && is_incomplete(&input) {
+            continue;
+        }
+
+        if !input.trim().is_empty() {
+            if let Err(error) = repl.eval(&input) {
+                report(&error, &input, "repl");
+            }
+        }
+        input.clear();
+    }
+
+    if let Some(path) = &history {
+        if let Err(error) = editor.save_history(path) {
+            eprintln!("Could not save history to '{}': {}", path.display(), error);
+        }
+    }
+}
+
+/// The history file in the user's home directory.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".untitledlang_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn eval_all(inputs: &[&str]) -> (Vec<String>, Vec<bool>) {
+        let mut output = vec![];
+        let mut results = vec![];
+
+        let mut repl = Repl::with_stdout(Cursor::new(&mut output));
+        for input in inputs {
+            results.push(repl.eval(input).is_ok());
+        }
+        drop(repl);
+
+        let output = String::from_utf8(output).unwrap();
+        (output.lines().map(|l| l.to_owned()).collect(), results)
+    }
+
+    #[test]
+    fn globals_persist() {
+        let (output, results) = eval_all(&[
+            "var x = 1",
+            "def inc(n)\n    return n + 1\nend",
+            "x = inc(x)",
+            "print(x)",
+        ]);
+
+        assert_eq!(vec!["2"], output);
+        assert_eq!(vec![true; 4], results);
+    }
+
+    #[test]
+    fn echo_bare_expressions() {
+        let (output, _) = eval_all(&["val a = 20", "a + 22", "\"str\"", "nil", "a", "\"a is $a\""]);
+
+        assert_eq!(vec!["42", "str", "20", "a is 20"], output);
+    }
+
+    #[test]
+    fn errors_do_not_end_session() {
+        let (output, results) = eval_all(&[
+            "val a = 1",
+            "a = 2",
+            "print(a + missing)",
+            "print(a",
+            "print(a)",
+        ]);
+
+        assert_eq!(vec!["1"], output);
+        assert_eq!(vec![true, false, false, false, true], results);
+    }
+
+    #[test]
+    fn globals_and_reset() {
+        let mut output = vec![];
+        let mut repl = Repl::with_stdout(Cursor::new(&mut output));
+
+        repl.eval("val b = \"two\"\nvar a = 1").unwrap();
+        assert!(repl.command(Command::Globals));
+        assert!(repl.command(Command::Reset));
+        assert!(repl.command(Command::Globals));
+        // 'b' is no longer known to be immutable, just undefined.
+        assert!(matches!(
+            repl.eval("b = 3"),
+            Err(InterpretError::Runtime(_))
+        ));
+        assert!(!repl.command(Command::Quit));
+        drop(repl);
+
+        assert_eq!("a = 1\nb = two\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn disasm_and_time() {
+        let mut output = vec![];
+        let mut repl = Repl::with_stdout(Cursor::new(&mut output));
+
+        repl.command(Command::Disasm("print(1)".to_string()));
+        repl.command(Command::Time("1 + 1".to_string()));
+        drop(repl);
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!("== <script> chunk ==", lines[0]);
+        assert!(lines.contains(&"2"));
+        assert!(lines.last().unwrap().starts_with("Took "));
+    }
+
+    #[test]
+    fn complete_defined_globals() {
+        let mut repl = Repl::with_stdout(Cursor::new(vec![]));
+        assert_eq!((0, vec![]), repl.complete("fib", 3));
+
+        repl.eval("def fibonacci(n)\n    return n\nend").unwrap();
+        assert_eq!((0, vec!["fibonacci".to_string()]), repl.complete("fib", 3));
+        assert_eq!((0, vec!["print".to_string()]), repl.complete("pri", 3));
+        // Built-in functions are completed too.
+        assert_eq!(
+            (
+                0,
+                vec!["pop".to_string(), "print".to_string(), "push".to_string()]
+            ),
+            repl.complete("p", 1)
+        );
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Ok(Command::Help), Command::parse(":help"));
+        assert_eq!(
+            Ok(Command::Load("test/closure/counter.un".to_string())),
+            Command::parse(":load  test/closure/counter.un ")
+        );
+        assert_eq!(
+            Ok(Command::Time("fib(20) + 1".to_string())),
+            Command::parse(":time fib(20) + 1")
+        );
+        assert_eq!(Ok(Command::Quit), Command::parse(":q"));
+        assert_eq!(
+            "Usage: :disasm <code>",
+            Command::parse(":disasm").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Unknown command ':frobnicate', try ':help'.",
+            Command::parse(":frobnicate").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(is_incomplete("def f()"));
+        assert!(is_incomplete("def f()\n    print(1)\n"));
+        assert!(is_incomplete("while x < 1 do"));
+        assert!(is_incomplete("{\n    val a = 1"));
+        assert!(is_incomplete("print(\"abc"));
+        assert!(is_incomplete("print(1 +"));
+
+        assert!(!is_incomplete("def f()\n    print(1)\nend"));
+        assert!(!is_incomplete("{ val a = 1 }"));
+        assert!(!is_incomplete("print(1))"));
+        assert!(!is_incomplete(""));
+    }
+}
@@ -0,0 +1,1031 @@
+use crate::lexer::token::Position;
+use crate::parser::ast::{
+    BinaryOperator, BlockDecl, ClassDecl, Expr, ExprKind, FunDecl, FunSignature, Identifier,
+    LiteralExpr, ModuleAst, Param, Pattern, TypeExpr, TypeExprKind, TypeParam, UnaryOperator,
+    VariantDecl,
+};
+use crate::typechecker::error::TypeError;
+use crate::typechecker::types::{Signature, Type};
+use std::collections::{HashMap, HashSet};
+
+/// The type of a variable. Only assignments to annotated variables are
+/// checked, any other `var` can hold values of any type.
+#[derive(Clone)]
+struct Binding {
+    type_: Type,
+    annotated: bool,
+}
+
+#[derive(Default)]
+struct ClassInfo {
+    type_params: Vec<TypeParam>,
+    fields: Vec<(Identifier, Type)>,
+    methods: HashMap<Identifier, Signature>,
+}
+
+#[derive(Default)]
+struct EnumInfo {
+    type_params: Vec<TypeParam>,
+    variants: Vec<(Identifier, Vec<(Identifier, Type)>)>,
+}
+
+/// The types inferred for the type parameters of a generic call, and the
+/// argument each was inferred from.
+type Inferred = HashMap<Identifier, (Type, Position)>;
+
+/// Walks the module inferring what it can about the type of each
+/// expression. A mismatch is only reported where a value flows into an
+/// annotated binding, parameter, field or return type.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<Identifier, Binding>>,
+    classes: HashMap<Identifier, ClassInfo>,
+    enums: HashMap<Identifier, EnumInfo>,
+    /// Every trait and the signatures of the methods it requires.
+    traits: HashMap<Identifier, HashMap<Identifier, Signature>>,
+    impls: HashSet<(Identifier, Identifier)>,
+    /// The type parameters of the generics being checked.
+    generics: Vec<TypeParam>,
+    /// The name and return type of every function being checked.
+    functions: Vec<(Identifier, Type)>,
+    /// What `self` is in every method being checked.
+    receivers: Vec<Type>,
+    errors: Vec<(TypeError, Position)>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            classes: HashMap::new(),
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            impls: HashSet::new(),
+            generics: vec![],
+            functions: vec![],
+            receivers: vec![],
+            errors: vec![],
+        }
+    }
+
+    pub fn into_errors(self) -> Vec<(TypeError, Position)> {
+        self.errors
+    }
+
+    pub fn check_module(&mut self, ast: &ModuleAst) {
+        // Types and top-level functions can be used before they are
+        // declared, so their names and signatures are collected first.
+        for expr in ast {
+            self.declare_type(expr);
+        }
+        for expr in ast {
+            self.declare_signatures(expr);
+        }
+
+        for expr in ast {
+            self.check_expr(expr);
+        }
+    }
+
+    fn declare_type(&mut self, expr: &Expr) {
+        match expr.kind() {
+            ExprKind::Class { ident, decl } => {
+                let info = self.classes.entry(ident.clone()).or_default();
+                info.type_params = decl.type_params().to_vec();
+            }
+            ExprKind::Enum {
+                ident, type_params, ..
+            } => {
+                let info = self.enums.entry(ident.clone()).or_default();
+                info.type_params = type_params.clone();
+            }
+            ExprKind::Trait { ident, .. } => {
+                self.traits.entry(ident.clone()).or_default();
+            }
+            _ => {}
+        }
+    }
+
+    fn declare_signatures(&mut self, expr: &Expr) {
+        let position = *expr.position();
+        match expr.kind() {
+            ExprKind::Fun { ident, decl } => {
+                let signature = self.signature(ident, decl, &position);
+                self.declare(ident, Type::function(signature), false);
+            }
+            ExprKind::Class { ident, decl } => self.declare_class(ident, decl, &position),
+            ExprKind::Method { class, ident, decl } => {
+                self.declare_method(class, ident, decl, &position);
+            }
+            ExprKind::Trait { ident, methods } => self.declare_trait(ident, methods),
+            ExprKind::Enum {
+                ident,
+                type_params,
+                variants,
+            } => self.declare_enum(ident, type_params, variants, &position),
+            ExprKind::Impl {
+                trait_,
+                class,
+                methods,
+            } => {
+                self.impls.insert((class.clone(), trait_.clone()));
+                for (ident, decl) in methods {
+                    self.declare_method(class, ident, decl, &position);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn declare_enum(
+        &mut self,
+        ident: &Identifier,
+        type_params: &[TypeParam],
+        variants: &[VariantDecl],
+        position: &Position,
+    ) {
+        let variants = self.with_type_params(type_params, position, |checker| {
+            variants
+                .iter()
+                .map(|variant| {
+                    let fields = checker.resolve_params(variant.fields());
+                    (variant.ident().clone(), fields)
+                })
+                .collect()
+        });
+
+        let info = self.enums.entry(ident.clone()).or_default();
+        info.type_params = type_params.to_vec();
+        info.variants = variants;
+    }
+
+    fn declare_class(&mut self, ident: &Identifier, decl: &ClassDecl, position: &Position) {
+        let fields = self.with_type_params(decl.type_params(), position, |checker| {
+            decl.fields()
+                .iter()
+                .map(|field| {
+                    let type_ = checker.resolve_optional(field.type_());
+                    (field.ident().clone(), type_)
+                })
+                .collect()
+        });
+
+        let info = self.classes.entry(ident.clone()).or_default();
+        info.type_params = decl.type_params().to_vec();
+        info.fields = fields;
+
+        for (method, decl) in decl.methods() {
+            self.declare_method(ident, method, decl, position);
+        }
+    }
+
+    /// Records the signature of a method, whose annotations can name the
+    /// type parameters of its class.
+    fn declare_method(
+        &mut self,
+        class: &Identifier,
+        ident: &Identifier,
+        decl: &FunDecl,
+        position: &Position,
+    ) -> Signature {
+        let type_params = self.class_type_params(class);
+        let signature = self.with_type_params(&type_params, position, |checker| {
+            checker.signature(ident, decl, position)
+        });
+        if let Some(info) = self.classes.get_mut(class) {
+            info.methods.insert(ident.clone(), signature.clone());
+        }
+
+        signature
+    }
+
+    fn declare_trait(&mut self, ident: &Identifier, methods: &[FunSignature]) {
+        let methods = methods
+            .iter()
+            .map(|method| {
+                let params = self.resolve_params(method.args());
+                let ret = self.resolve_optional(method.return_type());
+                let signature = Signature::new(method.ident().clone(), params, ret);
+                (method.ident().clone(), signature)
+            })
+            .collect();
+        self.traits.insert(ident.clone(), methods);
+    }
+
+    fn signature(&mut self, ident: &Identifier, decl: &FunDecl, position: &Position) -> Signature {
+        let (params, ret) = self.with_type_params(decl.type_params(), position, |checker| {
+            let params = checker.resolve_params(decl.args());
+            let ret = checker.resolve_optional(decl.return_type());
+            (params, ret)
+        });
+
+        Signature::generic(ident.clone(), decl.type_params().to_vec(), params, ret)
+    }
+
+    /// Runs `f` with the type parameters in scope, so annotations can name
+    /// them.
+    fn with_type_params<R>(
+        &mut self,
+        type_params: &[TypeParam],
+        position: &Position,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        for param in type_params {
+            if let Some(bound) = param.bound() {
+                if !self.traits.contains_key(bound) {
+                    self.error(TypeError::NotATrait(bound.clone()), position);
+                }
+            }
+        }
+
+        let depth = self.generics.len();
+        self.generics.extend(type_params.iter().cloned());
+        let result = f(self);
+        self.generics.truncate(depth);
+
+        result
+    }
+
+    fn class_type_params(&self, class: &str) -> Vec<TypeParam> {
+        self.classes
+            .get(class)
+            .map_or(vec![], |info| info.type_params.clone())
+    }
+
+    fn resolve_params(&mut self, params: &[Param]) -> Vec<(Identifier, Type)> {
+        params
+            .iter()
+            .map(|param| {
+                let type_ = self.resolve_optional(param.type_());
+                (param.ident().clone(), type_)
+            })
+            .collect()
+    }
+
+    /// The type an annotation names, `Any` for a missing annotation.
+    fn resolve_optional(&mut self, type_: Option<&TypeExpr>) -> Type {
+        match type_ {
+            Some(type_) => self.resolve(type_),
+            None => Type::Any,
+        }
+    }
+
+    fn resolve(&mut self, type_: &TypeExpr) -> Type {
+        let position = type_.position();
+        let (name, args) = match type_.kind() {
+            TypeExprKind::Named(name) => (name, vec![]),
+            TypeExprKind::Generic(name, args) => {
+                let args = args.iter().map(|arg| self.resolve(arg)).collect();
+                (name, args)
+            }
+        };
+
+        let param = self
+            .generics
+            .iter()
+            .rev()
+            .find(|param| param.ident() == name)
+            .cloned();
+        if let Some(param) = param {
+            self.type_args(name, args, 0, position);
+            return Type::param(&param);
+        }
+
+        let type_ = match name.as_str() {
+            "any" => Type::Any,
+            "number" | "int" | "float" => Type::Number,
+            "bool" => Type::Bool,
+            "nil" => Type::Nil,
+            "string" | "String" => Type::String,
+            "range" => Type::Range,
+            "function" => Type::Function(None),
+            "list" | "List" => {
+                let args = self.type_args(name, args, 1, position);
+                return Type::list(args[0].clone());
+            }
+            "map" | "Map" => {
+                let args = self.type_args(name, args, 2, position);
+                return Type::map(args[0].clone(), args[1].clone());
+            }
+            _ if self.classes.contains_key(name) => {
+                let arity = self.classes[name].type_params.len();
+                let args = self.type_args(name, args, arity, position);
+                return Type::Instance(name.clone(), args);
+            }
+            _ if self.enums.contains_key(name) => {
+                let arity = self.enums[name].type_params.len();
+                let args = self.type_args(name, args, arity, position);
+                return Type::Variant(name.clone(), args);
+            }
+            _ if self.traits.contains_key(name) => Type::Trait(name.clone()),
+            _ => {
+                self.error(TypeError::UnknownType(name.clone()), position);
+                return Type::Any;
+            }
+        };
+        self.type_args(name, args, 0, position);
+
+        type_
+    }
+
+    /// The type arguments given to a generic type, all `any` if there are
+    /// none.
+    fn type_args(
+        &mut self,
+        name: &str,
+        args: Vec<Type>,
+        arity: usize,
+        position: &Position,
+    ) -> Vec<Type> {
+        if args.is_empty() {
+            return vec![Type::Any; arity];
+        }
+        if args.len() != arity {
+            let error = TypeError::TypeArguments(name.to_string(), arity, args.len());
+            self.error(error, position);
+            return vec![Type::Any; arity];
+        }
+
+        args
+    }
+
+    /// Whether a value of type `found` can be used where `expected` is.
+    fn fits(&self, found: &Type, expected: &Type) -> bool {
+        match (found, expected) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function(_), Type::Function(None)) => true,
+            (Type::List(found), Type::List(expected)) => self.fits(found, expected),
+            (Type::Map(found_key, found_value), Type::Map(key, value)) => {
+                self.fits(found_key, key) && self.fits(found_value, value)
+            }
+            (Type::Instance(found_name, found), Type::Instance(name, expected))
+            | (Type::Variant(found_name, found), Type::Variant(name, expected)) => {
+                found_name == name
+                    && found.len() == expected.len()
+                    && found
+                        .iter()
+                        .zip(expected)
+                        .all(|(found, expected)| self.fits(found, expected))
+            }
+            (Type::Instance(class, _), Type::Trait(trait_)) => {
+                self.impls.contains(&(class.clone(), trait_.clone()))
+            }
+            (Type::Param(_, Some(bound)), Type::Trait(trait_)) => bound == trait_,
+            (found, expected) => found == expected,
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        let position = *expr.position();
+        match expr.kind() {
+            ExprKind::Grouping { expr } => self.check_expr(expr),
+            ExprKind::Binary { left, op, right } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                binary_type(op, left, right)
+            }
+            ExprKind::Unary { op, expr } => {
+                self.check_expr(expr);
+                match op {
+                    UnaryOperator::Negate => Type::Number,
+                    UnaryOperator::Not => Type::Bool,
+                }
+            }
+            ExprKind::LetAssign {
+                ident,
+                type_,
+                initializer,
+                mutable,
+            } => {
+                let found = self.check_expr(initializer);
+                match type_ {
+                    Some(type_) => {
+                        let expected = self.resolve(type_);
+                        if !self.fits(&found, &expected) {
+                            let error = TypeError::Binding(ident.clone(), expected.clone(), found);
+                            self.error(error, initializer.position());
+                        }
+                        self.declare(ident, expected, true);
+                    }
+                    // A val never changes, so it keeps the type of its value.
+                    None if !mutable => self.declare(ident, unannotated(found), false),
+                    None => self.declare(ident, Type::Any, false),
+                }
+                Type::Nil
+            }
+            ExprKind::LetGet { ident } => self
+                .lookup(ident)
+                .map_or(Type::Any, |binding| binding.type_.clone()),
+            ExprKind::LetSet { ident, expr } => {
+                let found = self.check_expr(expr);
+                self.assign(ident, &found, expr.position());
+                found
+            }
+            ExprKind::Fun { ident, decl } => {
+                let signature = self.signature(ident, decl, &position);
+                self.declare(ident, Type::function(signature.clone()), false);
+                self.check_function(&signature, decl, None, &position);
+                Type::Nil
+            }
+            ExprKind::Class { ident, decl } => {
+                if !self.classes.contains_key(ident) {
+                    self.declare_class(ident, decl, &position);
+                }
+                self.declare(ident, Type::Class(ident.clone()), false);
+
+                for (method, decl) in decl.methods() {
+                    self.check_method(ident, method, decl, &position);
+                }
+                Type::Nil
+            }
+            ExprKind::Method { class, ident, decl } => {
+                self.check_method(class, ident, decl, &position);
+                Type::Nil
+            }
+            ExprKind::Trait { ident, methods } => {
+                if !self.traits.contains_key(ident) {
+                    self.declare_trait(ident, methods);
+                }
+                self.declare(ident, Type::Any, false);
+                Type::Nil
+            }
+            ExprKind::Enum {
+                ident,
+                type_params,
+                variants,
+            } => {
+                if !self.enums.contains_key(ident) {
+                    self.declare_enum(ident, type_params, variants, &position);
+                }
+                self.declare(ident, Type::Enum(ident.clone()), false);
+                Type::Nil
+            }
+            ExprKind::Impl {
+                trait_,
+                class,
+                methods,
+            } => {
+                self.impls.insert((class.clone(), trait_.clone()));
+                for (ident, decl) in methods {
+                    self.check_method(class, ident, decl, &position);
+                }
+                Type::Nil
+            }
+            ExprKind::GetProperty { target, ident } => {
+                let target = self.check_expr(target);
+                self.property_type(&target, ident)
+            }
+            ExprKind::SetProperty {
+                target,
+                ident,
+                expr,
+            } => {
+                let target = self.check_expr(target);
+                let found = self.check_expr(expr);
+                if let Type::Instance(class, args) = &target {
+                    let expected = self.field_type(class, args, ident);
+                    if !self.fits(&found, &expected) {
+                        let error =
+                            TypeError::Field(class.clone(), ident.clone(), expected, found.clone());
+                        self.error(error, expr.position());
+                    }
+                }
+                found
+            }
+            ExprKind::SelfValue => self.receivers.last().cloned().unwrap_or(Type::Any),
+            ExprKind::Call { callee, args } => self.check_call(callee, args),
+            ExprKind::List { elements } => {
+                let elements: Vec<Type> = elements.iter().map(|e| self.check_expr(e)).collect();
+                Type::list(common_type(elements))
+            }
+            ExprKind::Map { entries } => {
+                let mut keys = vec![];
+                let mut values = vec![];
+                for (key, value) in entries {
+                    keys.push(self.check_expr(key));
+                    values.push(self.check_expr(value));
+                }
+                Type::map(common_type(keys), common_type(values))
+            }
+            ExprKind::Index { target, index } => {
+                let target = self.check_expr(target);
+                self.check_expr(index);
+                match target {
+                    Type::List(element) => *element,
+                    Type::Map(_, value) => *value,
+                    _ => Type::Any,
+                }
+            }
+            ExprKind::SetIndex {
+                target,
+                index,
+                expr,
+            } => {
+                self.check_expr(target);
+                self.check_expr(index);
+                self.check_expr(expr)
+            }
+            ExprKind::While { condition, body } => {
+                self.check_expr(condition);
+                self.check_expr(body);
+                Type::Nil
+            }
+            ExprKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                self.begin_scope();
+                for expr in init.iter().chain(condition).chain(step) {
+                    self.check_expr(expr);
+                }
+                self.check_expr(body);
+                self.end_scope();
+                Type::Nil
+            }
+            ExprKind::ForIn {
+                ident,
+                iterable,
+                body,
+            } => {
+                let element = match self.check_expr(iterable) {
+                    Type::List(element) => *element,
+                    Type::Range => Type::Number,
+                    _ => Type::Any,
+                };
+                self.begin_scope();
+                self.declare(ident, element, false);
+                self.check_expr(body);
+                self.end_scope();
+                Type::Nil
+            }
+            ExprKind::IfElse {
+                condition,
+                then,
+                else_,
+            } => {
+                self.check_expr(condition);
+                self.check_block(then);
+                if let Some(else_) = else_ {
+                    self.check_block(else_);
+                }
+                Type::Nil
+            }
+            ExprKind::Block { block } => {
+                self.check_block(block);
+                Type::Nil
+            }
+            ExprKind::Print { expr } => {
+                self.check_expr(expr);
+                Type::Nil
+            }
+            ExprKind::Return { expr } => {
+                let found = match expr {
+                    Some(expr) => self.check_expr(expr),
+                    None => Type::Nil,
+                };
+                if let Some((name, expected)) = self.functions.last().cloned() {
+                    if !self.fits(&found, &expected) {
+                        let position = expr.as_ref().map_or(position, |expr| *expr.position());
+                        self.error(TypeError::Return(name, expected, found), &position);
+                    }
+                }
+                Type::Nil
+            }
+            ExprKind::Match { expr, arms } => {
+                self.check_expr(expr);
+                for arm in arms {
+                    self.begin_scope();
+                    self.declare_pattern(arm.pattern());
+                    if let Some(guard) = arm.guard() {
+                        self.check_expr(guard);
+                    }
+                    self.check_expr(arm.body());
+                    self.end_scope();
+                }
+                Type::Any
+            }
+            ExprKind::Interpolation { parts } => {
+                for part in parts {
+                    self.check_expr(part);
+                }
+                Type::String
+            }
+            ExprKind::Break | ExprKind::Continue => Type::Nil,
+            ExprKind::Literal(literal) => match literal {
+                LiteralExpr::Number(_) => Type::Number,
+                LiteralExpr::String(_) => Type::String,
+                LiteralExpr::True | LiteralExpr::False => Type::Bool,
+                LiteralExpr::Nil => Type::Nil,
+            },
+        }
+    }
+
+    fn check_block(&mut self, block: &BlockDecl) {
+        self.begin_scope();
+        for expr in block {
+            self.check_expr(expr);
+        }
+        self.end_scope();
+    }
+
+    fn check_method(
+        &mut self,
+        class: &Identifier,
+        ident: &Identifier,
+        decl: &FunDecl,
+        position: &Position,
+    ) {
+        let signature = self.declare_method(class, ident, decl, position);
+
+        let type_params = self.class_type_params(class);
+        let receiver = if self.classes.contains_key(class) {
+            Type::Instance(class.clone(), type_params.iter().map(Type::param).collect())
+        } else {
+            Type::Any
+        };
+        self.with_type_params(&type_params, position, |checker| {
+            checker.check_function(&signature, decl, Some(receiver), position)
+        });
+    }
+
+    /// Checks the body of a function with its parameters in scope.
+    fn check_function(
+        &mut self,
+        signature: &Signature,
+        decl: &FunDecl,
+        receiver: Option<Type>,
+        position: &Position,
+    ) {
+        self.begin_scope();
+        for (param, type_) in signature.params() {
+            let annotated = type_ != &Type::Any;
+            self.declare(param, type_.clone(), annotated);
+        }
+        self.functions
+            .push((signature.name().clone(), signature.ret().clone()));
+        if let Some(receiver) = &receiver {
+            self.receivers.push(receiver.clone());
+        }
+
+        self.with_type_params(signature.type_params(), position, |checker| {
+            for expr in decl.body() {
+                checker.check_expr(expr);
+            }
+        });
+
+        let ret = signature.ret();
+        if !matches!(ret, Type::Any | Type::Nil) && !always_returns(decl.body()) {
+            let error = TypeError::MissingReturn(signature.name().clone(), ret.clone());
+            self.error(error, position);
+        }
+
+        if receiver.is_some() {
+            self.receivers.pop();
+        }
+        self.functions.pop();
+        self.end_scope();
+    }
+
+    fn check_call(&mut self, callee: &Expr, args: &[Expr]) -> Type {
+        let callee = self.check_expr(callee);
+        let found: Vec<Type> = args.iter().map(|arg| self.check_expr(arg)).collect();
+
+        let signature = match callee {
+            Type::Function(Some(signature)) => *signature,
+            Type::Class(class) => self.constructor(&class),
+            _ => return Type::Any,
+        };
+        self.check_arguments(&signature, args, &found)
+    }
+
+    /// Checks the arguments of a call and returns the type of its result.
+    /// The types that the type parameters of a generic function stand for
+    /// are inferred from the arguments.
+    fn check_arguments(&mut self, signature: &Signature, args: &[Expr], found: &[Type]) -> Type {
+        let mut inferred = Inferred::new();
+        for ((_, expected), (arg, found)) in signature.params().iter().zip(args.iter().zip(found)) {
+            infer(
+                expected,
+                found,
+                signature.type_params(),
+                &mut inferred,
+                arg.position(),
+            );
+        }
+
+        for param in signature.type_params() {
+            if let (Some(bound), Some((type_, position))) =
+                (param.bound(), inferred.get(param.ident()))
+            {
+                if !self.fits(type_, &Type::Trait(bound.clone())) {
+                    let error = TypeError::Bound(
+                        signature.name().clone(),
+                        param.ident().clone(),
+                        bound.clone(),
+                        type_.clone(),
+                    );
+                    self.error(error, position);
+                }
+            }
+        }
+
+        // Type parameters that couldn't be inferred can be anything.
+        let mut types: HashMap<Identifier, Type> = inferred
+            .into_iter()
+            .map(|(name, (type_, _))| (name, type_))
+            .collect();
+        for param in signature.type_params() {
+            types.entry(param.ident().clone()).or_insert(Type::Any);
+        }
+
+        for ((param, expected), (arg, found)) in
+            signature.params().iter().zip(args.iter().zip(found))
+        {
+            let expected = expected.substitute(&types);
+            if !self.fits(found, &expected) {
+                let error = TypeError::Argument(
+                    signature.name().clone(),
+                    param.clone(),
+                    expected,
+                    found.clone(),
+                );
+                self.error(error, arg.position());
+            }
+        }
+
+        signature.ret().substitute(&types)
+    }
+
+    /// Calling a class creates an instance from a value for each field.
+    fn constructor(&self, class: &Identifier) -> Signature {
+        let (type_params, fields) = self.classes.get(class).map_or((vec![], vec![]), |info| {
+            (info.type_params.clone(), info.fields.clone())
+        });
+        let instance = Type::Instance(class.clone(), type_params.iter().map(Type::param).collect());
+
+        Signature::generic(class.clone(), type_params, fields, instance)
+    }
+
+    fn property_type(&self, target: &Type, ident: &str) -> Type {
+        match target {
+            Type::Instance(class, args) => {
+                let info = match self.classes.get(class) {
+                    Some(info) => info,
+                    None => return Type::Any,
+                };
+                let types = bind(&info.type_params, args);
+                if let Some((_, type_)) = info.fields.iter().find(|(field, _)| field == ident) {
+                    return type_.substitute(&types);
+                }
+                info.methods.get(ident).map_or(Type::Any, |signature| {
+                    Type::function(signature.substitute(&types))
+                })
+            }
+            Type::Class(class) => {
+                let info = match self.classes.get(class) {
+                    Some(info) => info,
+                    None => return Type::Any,
+                };
+                let types = bind(&info.type_params, &[]);
+                info.methods.get(ident).map_or(Type::Any, |signature| {
+                    Type::function(signature.substitute(&types))
+                })
+            }
+            Type::Enum(enum_) => {
+                let info = match self.enums.get(enum_) {
+                    Some(info) => info,
+                    None => return Type::Any,
+                };
+                match info.variants.iter().find(|(variant, _)| variant == ident) {
+                    Some((_, fields)) if fields.is_empty() => {
+                        Type::Variant(enum_.clone(), vec![Type::Any; info.type_params.len()])
+                    }
+                    Some((variant, fields)) => {
+                        let params = info.type_params.iter().map(Type::param).collect();
+                        Type::function(Signature::generic(
+                            variant.clone(),
+                            info.type_params.clone(),
+                            fields.clone(),
+                            Type::Variant(enum_.clone(), params),
+                        ))
+                    }
+                    None => Type::Any,
+                }
+            }
+            // Only the methods of the trait are known.
+            Type::Trait(trait_) | Type::Param(_, Some(trait_)) => self
+                .traits
+                .get(trait_)
+                .and_then(|methods| methods.get(ident))
+                .map_or(Type::Any, |signature| Type::function(signature.clone())),
+            _ => Type::Any,
+        }
+    }
+
+    fn field_type(&self, class: &str, args: &[Type], ident: &str) -> Type {
+        self.classes
+            .get(class)
+            .and_then(|info| {
+                let (_, type_) = info.fields.iter().find(|(field, _)| field == ident)?;
+                Some(type_.substitute(&bind(&info.type_params, args)))
+            })
+            .unwrap_or(Type::Any)
+    }
+
+    /// Bindings in a pattern hold parts of the matched value, whose type
+    /// isn't known.
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(ident) => self.declare(ident, Type::Any, false),
+            Pattern::Variant { fields, .. } => {
+                for field in fields {
+                    self.declare_pattern(field);
+                }
+            }
+            Pattern::List(elements) => {
+                for element in elements {
+                    self.declare_pattern(element);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => {}
+        }
+    }
+
+    /// Checks an assignment to an annotated variable. Any other variable can
+    /// now hold a value of any type.
+    fn assign(&mut self, ident: &str, found: &Type, position: &Position) {
+        let binding = match self.lookup(ident) {
+            Some(binding) => binding.clone(),
+            None => return,
+        };
+
+        if !binding.annotated {
+            self.declare_existing(ident, Type::Any);
+        } else if !self.fits(found, &binding.type_) {
+            let error = TypeError::Binding(ident.to_string(), binding.type_, found.clone());
+            self.error(error, position);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, ident: &Identifier, type_: Type, annotated: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(ident.clone(), Binding { type_, annotated });
+        }
+    }
+
+    /// Changes the type of the innermost variable named `ident`.
+    fn declare_existing(&mut self, ident: &str, type_: Type) {
+        if let Some(binding) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(ident))
+        {
+            binding.type_ = type_;
+        }
+    }
+
+    fn lookup(&self, ident: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(ident))
+    }
+
+    /// Records an error, the same error is only reported once even if the
+    /// code is checked again.
+    fn error(&mut self, error: TypeError, position: &Position) {
+        let error = (error, *position);
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+}
+
+/// The type of a binary expression, as far as it can be told from the
+/// types of its operands.
+fn binary_type(op: &BinaryOperator, left: Type, right: Type) -> Type {
+    match op {
+        BinaryOperator::Add => match (left, right) {
+            (Type::Number, Type::Number) => Type::Number,
+            (Type::String, Type::String) => Type::String,
+            _ => Type::Any,
+        },
+        BinaryOperator::Subtract | BinaryOperator::Multiply | BinaryOperator::Divide => {
+            Type::Number
+        }
+        BinaryOperator::Equal
+        | BinaryOperator::BangEqual
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::GreaterThanEqual
+        | BinaryOperator::LessThan
+        | BinaryOperator::LessThanEqual => Type::Bool,
+        BinaryOperator::Range => Type::Range,
+        // Either operand can be the result.
+        BinaryOperator::And | BinaryOperator::Or if left == right => left,
+        BinaryOperator::And | BinaryOperator::Or => Type::Any,
+    }
+}
+
+/// Works out the types that the type parameters of a generic function
+/// stand for, by matching the type a parameter expects against the type of
+/// its argument.
+fn infer(
+    expected: &Type,
+    found: &Type,
+    type_params: &[TypeParam],
+    inferred: &mut Inferred,
+    position: &Position,
+) {
+    match (expected, found) {
+        (_, Type::Any) => {}
+        (Type::Param(name, _), found) if type_params.iter().any(|p| p.ident() == name) => {
+            // The first argument decides, later ones are checked against it.
+            inferred
+                .entry(name.clone())
+                .or_insert_with(|| (found.clone(), *position));
+        }
+        (Type::List(expected), Type::List(found)) => {
+            infer(expected, found, type_params, inferred, position)
+        }
+        (Type::Map(key, value), Type::Map(found_key, found_value)) => {
+            infer(key, found_key, type_params, inferred, position);
+            infer(value, found_value, type_params, inferred, position);
+        }
+        (Type::Instance(name, expected), Type::Instance(found_name, found))
+        | (Type::Variant(name, expected), Type::Variant(found_name, found))
+            if name == found_name =>
+        {
+            for (expected, found) in expected.iter().zip(found) {
+                infer(expected, found, type_params, inferred, position);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Maps the type parameters of a generic class or enum to the types given
+/// for them, `any` for the ones that are missing.
+fn bind(type_params: &[TypeParam], args: &[Type]) -> HashMap<Identifier, Type> {
+    type_params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let type_ = args.get(i).cloned().unwrap_or(Type::Any);
+            (param.ident().clone(), type_)
+        })
+        .collect()
+}
+
+/// Whether running the block always ends in a `return`.
+fn always_returns(block: &[Expr]) -> bool {
+    block.iter().any(returns)
+}
+
+fn returns(expr: &Expr) -> bool {
+    match expr.kind() {
+        ExprKind::Return { .. } => true,
+        ExprKind::Block { block } => always_returns(block),
+        ExprKind::IfElse {
+            then,
+            else_: Some(else_),
+            ..
+        } => always_returns(then) && always_returns(else_),
+        // Matching a value no arm matches is a runtime error.
+        ExprKind::Match { arms, .. } => {
+            !arms.is_empty() && arms.iter().all(|arm| returns(arm.body()))
+        }
+        _ => false,
+    }
+}
+
+/// The type of a value bound without an annotation. The contents of a list
+/// or map can still change, so they are left dynamically typed.
+fn unannotated(type_: Type) -> Type {
+    match type_ {
+        Type::List(_) => Type::list(Type::Any),
+        Type::Map(..) => Type::map(Type::Any, Type::Any),
+        type_ => type_,
+    }
+}
+
+/// The type shared by all the values, `any` if they differ.
+fn common_type(types: Vec<Type>) -> Type {
+    let mut types = types.into_iter();
+    let first = match types.next() {
+        Some(first) => first,
+        None => return Type::Any,
+    };
+
+    if types.all(|type_| type_ == first) {
+        first
+    } else {
+        Type::Any
+    }
+}
@@ -0,0 +1,81 @@
+use crate::typechecker::types::Type;
+use std::fmt;
+use std::fmt::Formatter;
+
+#[derive(Debug, PartialEq)]
+pub enum TypeError {
+    UnknownType(String),
+    Binding(String, Type, Type),
+    Argument(String, String, Type, Type),
+    Return(String, Type, Type),
+    MissingReturn(String, Type),
+    Field(String, String, Type, Type),
+    TypeArguments(String, usize, usize),
+    Bound(String, String, String, Type),
+    NotATrait(String),
+}
+
+impl TypeError {
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            TypeError::UnknownType(_) => Some(
+                "use number, bool, string, nil, list, map, range, function, any \
+                 or the name of a class, enum, trait or type parameter"
+                    .to_string(),
+            ),
+            TypeError::MissingReturn(..) => {
+                Some("add a 'return' to every path through the function".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::UnknownType(name) => write!(f, "Unknown type '{}'.", name),
+            TypeError::Binding(name, expected, found) => write!(
+                f,
+                "Cannot assign {} to '{}' of type {}.",
+                found, name, expected
+            ),
+            TypeError::Argument(fun, param, expected, found) => write!(
+                f,
+                "Argument '{}' of '{}' expects {} but got {}.",
+                param, fun, expected, found
+            ),
+            TypeError::Return(fun, expected, found) => write!(
+                f,
+                "'{}' should return {} but returns {}.",
+                fun, expected, found
+            ),
+            TypeError::MissingReturn(fun, expected) => write!(
+                f,
+                "'{}' should return {} but can reach its end without returning.",
+                fun, expected
+            ),
+            TypeError::Field(class, field, expected, found) => write!(
+                f,
+                "Field '{}' of {} expects {} but got {}.",
+                field, class, expected, found
+            ),
+            TypeError::TypeArguments(name, expected, found) => write!(
+                f,
+                "'{}' takes {} type arguments but got {}.",
+                name, expected, found
+            ),
+            TypeError::Bound(fun, param, trait_, found) => write!(
+                f,
+                "Type parameter '{}' of '{}' must implement {} but got {}.",
+                param, fun, trait_, found
+            ),
+            TypeError::NotATrait(name) => write!(
+                f,
+                "Can only bound a type parameter by a trait, not '{}'.",
+                name
+            ),
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use crate::lexer::token::Position;
+use crate::parser::ast::ModuleAst;
+use crate::typechecker::checker::TypeChecker;
+use crate::typechecker::error::TypeError;
+
+mod checker;
+pub mod error;
+pub mod types;
+
+/// Checks the module against its type annotations, or returns every
+/// mismatch found. Code without annotations is left to the runtime.
+pub fn check(ast: &ModuleAst) -> Result<(), Vec<(TypeError, Position)>> {
+    let mut checker = TypeChecker::new();
+    checker.check_module(ast);
+
+    let errors = checker.into_errors();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
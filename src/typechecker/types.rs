@@ -0,0 +1,165 @@
+use crate::parser::ast::{Identifier, TypeParam};
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+
+/// The static type of a value. Code without annotations has type `Any`,
+/// which fits every other type so it is only checked at runtime.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Number,
+    Bool,
+    Nil,
+    String,
+    /// A list and the type of its elements.
+    List(Box<Type>),
+    /// A map and the types of its keys and values.
+    Map(Box<Type>, Box<Type>),
+    Range,
+    /// A function, with its signature if it is known.
+    Function(Option<Box<Signature>>),
+    /// A class itself, calling it creates an instance.
+    Class(Identifier),
+    /// An instance of a class and the types its type parameters stand for.
+    Instance(Identifier, Vec<Type>),
+    /// An enum itself, its variants are read as properties.
+    Enum(Identifier),
+    /// A value of an enum and the types its type parameters stand for.
+    Variant(Identifier, Vec<Type>),
+    /// An instance of a class that implements the trait.
+    Trait(Identifier),
+    /// A type parameter of the generic being checked, and the trait its
+    /// type has to implement.
+    Param(Identifier, Option<Identifier>),
+}
+
+impl Type {
+    pub fn function(signature: Signature) -> Self {
+        Type::Function(Some(Box::new(signature)))
+    }
+
+    pub fn list(element: Type) -> Self {
+        Type::List(Box::new(element))
+    }
+
+    pub fn map(key: Type, value: Type) -> Self {
+        Type::Map(Box::new(key), Box::new(value))
+    }
+
+    pub fn param(param: &TypeParam) -> Self {
+        Type::Param(param.ident().clone(), param.bound().cloned())
+    }
+
+    /// Replaces the type parameters in the type by the types they stand for.
+    pub fn substitute(&self, types: &HashMap<Identifier, Type>) -> Type {
+        let all = |types_: &[Type]| types_.iter().map(|t| t.substitute(types)).collect();
+        match self {
+            Type::Param(name, _) => types.get(name).cloned().unwrap_or_else(|| self.clone()),
+            Type::List(element) => Type::list(element.substitute(types)),
+            Type::Map(key, value) => Type::map(key.substitute(types), value.substitute(types)),
+            Type::Instance(name, args) => Type::Instance(name.clone(), all(args)),
+            Type::Variant(name, args) => Type::Variant(name.clone(), all(args)),
+            Type::Function(Some(signature)) => Type::function(signature.substitute(types)),
+            _ => self.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Number => write!(f, "number"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::String => write!(f, "string"),
+            Type::List(element) if **element == Type::Any => write!(f, "list"),
+            Type::List(element) => write!(f, "list<{}>", element),
+            Type::Map(key, value) if **key == Type::Any && **value == Type::Any => {
+                write!(f, "map")
+            }
+            Type::Map(key, value) => write!(f, "map<{}, {}>", key, value),
+            Type::Range => write!(f, "range"),
+            Type::Function(_) => write!(f, "function"),
+            Type::Class(name) => write!(f, "class {}", name),
+            Type::Enum(name) => write!(f, "enum {}", name),
+            Type::Instance(name, args) | Type::Variant(name, args) => {
+                write!(f, "{}", name)?;
+                if args.iter().any(|arg| arg != &Type::Any) {
+                    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                    write!(f, "<{}>", args.join(", "))?;
+                }
+                Ok(())
+            }
+            Type::Trait(name) | Type::Param(name, _) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// The name, type parameters, parameter types and return type of a
+/// function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    name: Identifier,
+    type_params: Vec<TypeParam>,
+    params: Vec<(Identifier, Type)>,
+    ret: Type,
+}
+
+impl Signature {
+    pub fn new(name: Identifier, params: Vec<(Identifier, Type)>, ret: Type) -> Self {
+        Signature::generic(name, vec![], params, ret)
+    }
+
+    pub fn generic(
+        name: Identifier,
+        type_params: Vec<TypeParam>,
+        params: Vec<(Identifier, Type)>,
+        ret: Type,
+    ) -> Self {
+        Signature {
+            name,
+            type_params,
+            params,
+            ret,
+        }
+    }
+
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    pub fn type_params(&self) -> &[TypeParam] {
+        &self.type_params
+    }
+
+    pub fn params(&self) -> &[(Identifier, Type)] {
+        &self.params
+    }
+
+    pub fn ret(&self) -> &Type {
+        &self.ret
+    }
+
+    /// Replaces type parameters of an enclosing generic, the signature's
+    /// own type parameters are left alone.
+    pub fn substitute(&self, types: &HashMap<Identifier, Type>) -> Signature {
+        let mut types = types.clone();
+        for param in &self.type_params {
+            types.remove(param.ident());
+        }
+
+        let params = self
+            .params
+            .iter()
+            .map(|(name, type_)| (name.clone(), type_.substitute(&types)))
+            .collect();
+        Signature::generic(
+            self.name.clone(),
+            self.type_params.clone(),
+            params,
+            self.ret.substitute(&types),
+        )
+    }
+}
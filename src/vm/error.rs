@@ -1,4 +1,15 @@
+use crate::diagnostic::{Diagnostic, Severity};
+use std::fmt;
+use std::fmt::Formatter;
+
 pub type RunResult<T> = std::result::Result<T, RuntimeError>;
+pub type InterpretResult<T> = std::result::Result<T, InterpretError>;
+
+#[derive(Debug)]
+pub enum InterpretError {
+    Compile(Vec<Diagnostic>),
+    Runtime(Traceback),
+}
 
 #[derive(Debug)]
 pub enum RuntimeError {
@@ -6,8 +17,218 @@ pub enum RuntimeError {
     StackEmpty,
     FrameEmpty,
     InvalidCallee,
-    IncorrectArity,
+    IncorrectArity(u8, u8),
     BadStackIndex(usize, usize),
     UndefinedGlobal(String),
     ReturnFromTopLevel,
+    InvalidOperand(&'static str, &'static str),
+    InvalidOperands(&'static str, &'static str, &'static str),
+    NotIterable(&'static str),
+    NotIndexable(&'static str),
+    InvalidIndex(String),
+    IndexOutOfBounds(i64, usize),
+    InvalidArgument(&'static str, &'static str, &'static str),
+    EmptyList,
+    Unhashable(&'static str),
+    MissingKey(String),
+    UndefinedProperty(String, String),
+    NoProperties(String, &'static str),
+    AssignToImmutableField(String),
+    InvalidMethodTarget(&'static str),
+    NotATrait(&'static str),
+    MissingTraitMethod(String, String, String),
+    TraitMethodArity(String, u8, String, u8),
+    NotAnEnum(&'static str),
+    PatternArity(String, usize, u8),
+    NoMatch(String),
+}
+
+impl RuntimeError {
+    /// A suggestion on how to fix the error.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            RuntimeError::UndefinedGlobal(name) => Some(format!(
+                "declare '{}' with 'val' or 'var' before using it",
+                name
+            )),
+            RuntimeError::InvalidOperands("+", "string", _)
+            | RuntimeError::InvalidOperands("+", _, "string") => {
+                Some("only two strings can be concatenated".to_string())
+            }
+            RuntimeError::IndexOutOfBounds(_, 0) => Some("the list is empty".to_string()),
+            RuntimeError::IndexOutOfBounds(_, len) => Some(format!(
+                "valid indices are 0 to {}, or -{} to -1 counting from the end",
+                len - 1,
+                len
+            )),
+            RuntimeError::AssignToImmutableField(name) => {
+                Some(format!("declare '{}' with 'var' to make it mutable", name))
+            }
+            RuntimeError::MissingTraitMethod(class, method, _) => Some(format!(
+                "add it to the 'impl' block or define it with 'def ({}) {}(...)'",
+                class, method
+            )),
+            RuntimeError::MissingKey(_) => {
+                Some("use 'has' to check whether a map contains a key".to_string())
+            }
+            RuntimeError::NoMatch(_) => {
+                Some("add a '_' arm to handle every other value".to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::ArgumentTypes => write!(f, "Invalid argument types."),
+            RuntimeError::StackEmpty => write!(f, "Stack is empty."),
+            RuntimeError::FrameEmpty => write!(f, "No call frame."),
+            RuntimeError::InvalidCallee => write!(f, "Can only call functions."),
+            RuntimeError::IncorrectArity(expected, got) => {
+                write!(f, "Expected {} arguments but got {}.", expected, got)
+            }
+            RuntimeError::BadStackIndex(index, len) => {
+                write!(f, "Bad stack index {} (stack size {}).", index, len)
+            }
+            RuntimeError::UndefinedGlobal(name) => write!(f, "Undefined variable '{}'.", name),
+            RuntimeError::ReturnFromTopLevel => write!(f, "Can't return from top-level code."),
+            RuntimeError::InvalidOperand(op, operand) => {
+                write!(f, "Unsupported operand type for '{}': {}.", op, operand)
+            }
+            RuntimeError::InvalidOperands(op, left, right) => write!(
+                f,
+                "Unsupported operand types for '{}': {} and {}.",
+                op, left, right
+            ),
+            RuntimeError::NotIterable(type_name) => write!(f, "Cannot iterate over {}.", type_name),
+            RuntimeError::NotIndexable(type_name) => write!(f, "Cannot index into {}.", type_name),
+            RuntimeError::InvalidIndex(index) => {
+                write!(f, "List index must be a whole number, not {}.", index)
+            }
+            RuntimeError::IndexOutOfBounds(index, len) => write!(
+                f,
+                "Index {} is out of bounds for a list of length {}.",
+                index, len
+            ),
+            RuntimeError::InvalidArgument(function, expected, found) => write!(
+                f,
+                "Expected {} as argument to '{}' but got {}.",
+                expected, function, found
+            ),
+            RuntimeError::EmptyList => write!(f, "Cannot pop from an empty list."),
+            RuntimeError::Unhashable(type_name) => {
+                write!(f, "Cannot use {} as a map key.", type_name)
+            }
+            RuntimeError::MissingKey(key) => write!(f, "Key {} not found in map.", key),
+            RuntimeError::UndefinedProperty(name, class) => {
+                write!(f, "Undefined property '{}' on {}.", name, class)
+            }
+            RuntimeError::NoProperties(name, type_name) => {
+                write!(f, "Cannot access property '{}' on {}.", name, type_name)
+            }
+            RuntimeError::AssignToImmutableField(name) => {
+                write!(f, "Cannot assign to immutable field '{}'.", name)
+            }
+            RuntimeError::InvalidMethodTarget(type_name) => {
+                write!(f, "Cannot define a method on {}.", type_name)
+            }
+            RuntimeError::NotATrait(type_name) => {
+                write!(f, "Can only implement traits, not {}.", type_name)
+            }
+            RuntimeError::MissingTraitMethod(class, method, trait_) => write!(
+                f,
+                "{} does not implement '{}' required by {}.",
+                class, method, trait_
+            ),
+            RuntimeError::TraitMethodArity(method, arity, trait_, expected) => write!(
+                f,
+                "'{}' takes {} arguments but {} requires {}.",
+                method, arity, trait_, expected
+            ),
+            RuntimeError::NotAnEnum(type_name) => {
+                write!(f, "Can only match variants of enums, not {}.", type_name)
+            }
+            RuntimeError::PatternArity(variant, expected, found) => write!(
+                f,
+                "{} carries {} values but the pattern has {}.",
+                variant, expected, found
+            ),
+            RuntimeError::NoMatch(value) => write!(f, "No arm matches {}.", value),
+        }
+    }
+}
+
+/// A runtime error together with the call stack at the moment it occurred,
+/// innermost call first.
+#[derive(Debug)]
+pub struct Traceback {
+    error: RuntimeError,
+    frames: Vec<TraceFrame>,
+}
+
+impl Traceback {
+    pub fn new(error: RuntimeError, frames: Vec<TraceFrame>) -> Self {
+        Traceback { error, frames }
+    }
+
+    pub fn error(&self) -> &RuntimeError {
+        &self.error
+    }
+
+    pub fn frames(&self) -> &[TraceFrame] {
+        &self.frames
+    }
+
+    /// A diagnostic pointing at the line the error occurred on, with the
+    /// call stack as notes.
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let line = self.frames.first().map_or(0, |frame| frame.line());
+        self.frames.iter().fold(
+            Diagnostic::at_line(Severity::Error, self.error.to_string(), line, source)
+                .with_hint(self.error.hint()),
+            |diagnostic, frame| diagnostic.with_note(frame.to_string()),
+        )
+    }
+}
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        for frame in &self.frames {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct TraceFrame {
+    line: usize,
+    function: String,
+}
+
+impl TraceFrame {
+    pub fn new(line: usize, function: String) -> Self {
+        TraceFrame { line, function }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn function(&self) -> &str {
+        &self.function
+    }
+}
+
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.function.is_empty() {
+            write!(f, "[line {}] in script", self.line)
+        } else {
+            write!(f, "[line {}] in {}()", self.line, self.function)
+        }
+    }
 }
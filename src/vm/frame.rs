@@ -21,10 +21,6 @@ impl CallFrame {
         &self.closure
     }
 
-    pub fn closure_mut(&mut self) -> &mut Gc<Closure> {
-        &mut self.closure
-    }
-
     pub fn ip(&self) -> &usize {
         &self.ip
     }
@@ -1,27 +1,29 @@
 use crate::compiler::compile;
-use crate::vm::error::RunResult;
+use crate::vm::error::{InterpretError, InterpretResult};
 use crate::vm::vm::VM;
 use std::io::Write;
 
-mod error;
+pub mod error;
 mod frame;
 mod gc;
+mod native;
 pub mod obj;
 pub mod opcode;
 mod run;
-mod vm;
-
-pub fn interpret(source: &str) {
-    // TODO: Report errors.
-    let fun = compile(source).unwrap();
+pub mod vm;
 
+pub fn interpret(source: &str) -> InterpretResult<()> {
+    let fun = compile(source).map_err(InterpretError::Compile)?;
     let mut vm = VM::new();
-    vm.interpret(fun).unwrap();
+    vm.interpret(fun)
+        .map(|_| ())
+        .map_err(InterpretError::Runtime)
 }
 
-pub fn interpret_with_stdout<W: Write>(source: &str, stdout: W) -> RunResult<()> {
-    // TODO: Report errors.
-    let fun = compile(source).unwrap();
+pub fn interpret_with_stdout<W: Write>(source: &str, stdout: W) -> InterpretResult<()> {
+    let fun = compile(source).map_err(InterpretError::Compile)?;
     let mut vm = VM::with_stdout(stdout);
     vm.interpret(fun)
+        .map(|_| ())
+        .map_err(InterpretError::Runtime)
 }
@@ -0,0 +1,107 @@
+use crate::compiler::object::{List, Map, Native};
+use crate::compiler::value::Value;
+use crate::vm::error::{RunResult, RuntimeError};
+use crate::vm::obj::Gc;
+
+/// The functions every script can call without defining them.
+pub const NATIVES: &[Native] = &[
+    Native::new("len", 1, len),
+    Native::new("push", 2, push),
+    Native::new("pop", 1, pop),
+    Native::new("keys", 1, keys),
+    Native::new("values", 1, values),
+    Native::new("has", 2, has),
+    Native::new("implements", 2, implements),
+];
+
+fn len(args: &[Value]) -> RunResult<Value> {
+    let len = match &args[0] {
+        Value::List(list) => list.len(),
+        Value::String(s) => s.chars().count(),
+        Value::Map(map) => map.len(),
+        other => {
+            return Err(RuntimeError::InvalidArgument(
+                "len",
+                "a list, map or string",
+                other.type_name(),
+            ))
+        }
+    };
+    Ok(Value::Number(len as f64))
+}
+
+fn push(args: &[Value]) -> RunResult<Value> {
+    let mut list = as_list("push", &args[0])?;
+    list.push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+fn pop(args: &[Value]) -> RunResult<Value> {
+    let mut list = as_list("pop", &args[0])?;
+    list.pop().ok_or(RuntimeError::EmptyList)
+}
+
+fn keys(args: &[Value]) -> RunResult<Value> {
+    let map = as_map("keys", &args[0])?;
+    let keys = map.entries().iter().map(|(key, _)| key.clone()).collect();
+    Ok(Value::List(Gc::new(List::new(keys))))
+}
+
+fn values(args: &[Value]) -> RunResult<Value> {
+    let map = as_map("values", &args[0])?;
+    let values = map
+        .entries()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    Ok(Value::List(Gc::new(List::new(values))))
+}
+
+fn has(args: &[Value]) -> RunResult<Value> {
+    let map = as_map("has", &args[0])?;
+    Ok(Value::Bool(map.contains(&args[1])?))
+}
+
+/// Whether the value is an instance of a class, or the class itself, that
+/// implements the trait.
+fn implements(args: &[Value]) -> RunResult<Value> {
+    let trait_ = match &args[1] {
+        Value::Trait(trait_) => *trait_,
+        other => {
+            return Err(RuntimeError::InvalidArgument(
+                "implements",
+                "a trait",
+                other.type_name(),
+            ))
+        }
+    };
+
+    let class = match &args[0] {
+        Value::Instance(instance) => instance.class(),
+        Value::Class(class) => *class,
+        _ => return Ok(Value::Bool(false)),
+    };
+    Ok(Value::Bool(class.implements(trait_)))
+}
+
+fn as_list(function: &'static str, value: &Value) -> RunResult<Gc<List>> {
+    match value {
+        Value::List(list) => Ok(*list),
+        other => Err(RuntimeError::InvalidArgument(
+            function,
+            "a list",
+            other.type_name(),
+        )),
+    }
+}
+
+fn as_map(function: &'static str, value: &Value) -> RunResult<Gc<Map>> {
+    match value {
+        Value::Map(map) => Ok(*map),
+        other => Err(RuntimeError::InvalidArgument(
+            function,
+            "a map",
+            other.type_name(),
+        )),
+    }
+}
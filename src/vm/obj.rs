@@ -40,6 +40,11 @@ impl<T: ?Sized> Gc<T> {
         }
     }
 
+    /// Whether both handles point at the same object.
+    pub fn ptr_eq(&self, other: &Gc<T>) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+
     pub fn is_marked(&self) -> bool {
         self.deref_non_null().mark.get()
     }
@@ -61,7 +66,7 @@ impl<T: ?Sized> Gc<T> {
 
         unsafe {
             // drop inner wrapper, and thus the value it owns
-            Box::from_raw(self.0);
+            drop(Box::from_raw(self.0));
         }
     }
 }
@@ -74,7 +79,7 @@ impl<T: Any> Gc<T> {
 
 impl<T: ?Sized> Clone for Gc<T> {
     fn clone(&self) -> Self {
-        Self(self.0)
+        *self
     }
 }
 
@@ -27,6 +27,30 @@ pub enum Opcode {
     Call,
     Print,
     Pop,
+    Nil,
+    GetUpvalue,
+    SetUpvalue,
+    CloseUpvalue,
+    GreaterEqual,
+    LessEqual,
+    Range,
+    ForIter,
+    BuildList,
+    GetIndex,
+    SetIndex,
+    BuildMap,
+    Class,
+    Method,
+    GetProperty,
+    SetProperty,
+    Trait,
+    Impl,
+    Enum,
+    MatchVariant,
+    MatchList,
+    VariantValue,
+    NoMatch,
+    Interpolate,
 }
 
 impl From<u8> for Opcode {
@@ -55,6 +79,30 @@ impl From<u8> for Opcode {
             0x14 => Opcode::Call,
             0x15 => Opcode::Print,
             0x16 => Opcode::Pop,
+            0x17 => Opcode::Nil,
+            0x18 => Opcode::GetUpvalue,
+            0x19 => Opcode::SetUpvalue,
+            0x1a => Opcode::CloseUpvalue,
+            0x1b => Opcode::GreaterEqual,
+            0x1c => Opcode::LessEqual,
+            0x1d => Opcode::Range,
+            0x1e => Opcode::ForIter,
+            0x1f => Opcode::BuildList,
+            0x20 => Opcode::GetIndex,
+            0x21 => Opcode::SetIndex,
+            0x22 => Opcode::BuildMap,
+            0x23 => Opcode::Class,
+            0x24 => Opcode::Method,
+            0x25 => Opcode::GetProperty,
+            0x26 => Opcode::SetProperty,
+            0x27 => Opcode::Trait,
+            0x28 => Opcode::Impl,
+            0x29 => Opcode::Enum,
+            0x2a => Opcode::MatchVariant,
+            0x2b => Opcode::MatchList,
+            0x2c => Opcode::VariantValue,
+            0x2d => Opcode::NoMatch,
+            0x2e => Opcode::Interpolate,
             _ => panic!("No opcode for byte: {}", byte),
         }
     }
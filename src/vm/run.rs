@@ -1,6 +1,9 @@
-use crate::compiler::object::Closure;
+use crate::compiler::object::{
+    BoundMethod, Class, Closure, Enum, Field, List, Map, Trait, Variant, VariantDef,
+};
 use crate::compiler::value::Value;
 use crate::vm::error::{RunResult, RuntimeError};
+use crate::vm::obj::Gc;
 use crate::vm::opcode::Opcode;
 use crate::vm::vm::VM;
 use std::io::Write;
@@ -16,7 +19,9 @@ impl<W: Write> VM<W> {
                 Opcode::Multiply => self.multiply()?,
                 Opcode::Divide => self.divide()?,
                 Opcode::Greater => self.greater()?,
+                Opcode::GreaterEqual => self.greater_equal()?,
                 Opcode::Less => self.less()?,
+                Opcode::LessEqual => self.less_equal()?,
                 Opcode::Equal => self.equal()?,
                 Opcode::Not => self.not()?,
                 Opcode::Negate => self.negate()?,
@@ -35,6 +40,28 @@ impl<W: Write> VM<W> {
                 Opcode::Pop => {
                     self.pop()?;
                 }
+                Opcode::Nil => self.push(Value::Nil),
+                Opcode::GetUpvalue => self.get_upvalue()?,
+                Opcode::SetUpvalue => self.set_upvalue()?,
+                Opcode::CloseUpvalue => self.close_upvalue()?,
+                Opcode::Range => self.range()?,
+                Opcode::ForIter => self.for_iter()?,
+                Opcode::BuildList => self.build_list()?,
+                Opcode::GetIndex => self.get_index()?,
+                Opcode::SetIndex => self.set_index()?,
+                Opcode::BuildMap => self.build_map()?,
+                Opcode::Class => self.class()?,
+                Opcode::Method => self.method()?,
+                Opcode::GetProperty => self.get_property()?,
+                Opcode::SetProperty => self.set_property()?,
+                Opcode::Trait => self.trait_()?,
+                Opcode::Impl => self.impl_()?,
+                Opcode::Enum => self.enum_()?,
+                Opcode::MatchVariant => self.match_variant()?,
+                Opcode::MatchList => self.match_list()?,
+                Opcode::VariantValue => self.variant_value()?,
+                Opcode::NoMatch => self.no_match()?,
+                Opcode::Interpolate => self.interpolate()?,
             }
         }
 
@@ -50,28 +77,28 @@ impl<W: Write> VM<W> {
     fn add(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push(a + b);
+        self.push((a + b)?);
         Ok(())
     }
 
     fn subtract(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push(a - b);
+        self.push((a - b)?);
         Ok(())
     }
 
     fn multiply(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push(a * b);
+        self.push((a * b)?);
         Ok(())
     }
 
     fn divide(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push(a / b);
+        self.push((a / b)?);
         Ok(())
     }
 
@@ -85,14 +112,28 @@ impl<W: Write> VM<W> {
     fn greater(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push((a > b).into());
+        self.push(a.greater(&b)?.into());
+        Ok(())
+    }
+
+    fn greater_equal(&mut self) -> RunResult<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(a.greater_equal(&b)?.into());
         Ok(())
     }
 
     fn less(&mut self) -> RunResult<()> {
         let b = self.pop()?;
         let a = self.pop()?;
-        self.push((a < b).into());
+        self.push(a.less(&b)?.into());
+        Ok(())
+    }
+
+    fn less_equal(&mut self) -> RunResult<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(a.less_equal(&b)?.into());
         Ok(())
     }
 
@@ -104,7 +145,7 @@ impl<W: Write> VM<W> {
 
     fn negate(&mut self) -> RunResult<()> {
         let a = self.pop()?;
-        self.push(-a);
+        self.push((-a)?);
         Ok(())
     }
 
@@ -129,6 +170,38 @@ impl<W: Write> VM<W> {
         Ok(())
     }
 
+    fn get_upvalue(&mut self) -> RunResult<()> {
+        let slot = self.read_byte()? as usize;
+        let upvalue = self.frame()?.closure().upvalues[slot];
+
+        let value = match upvalue.closed() {
+            Some(value) => value.clone(),
+            None => self.stack()[upvalue.location()].clone(),
+        };
+        self.push(value);
+        Ok(())
+    }
+
+    fn set_upvalue(&mut self) -> RunResult<()> {
+        let slot = self.read_byte()? as usize;
+        let value = self.peek()?.clone();
+        let mut upvalue = self.frame()?.closure().upvalues[slot];
+
+        if upvalue.closed().is_some() {
+            upvalue.close(value);
+        } else {
+            self.stack_mut()[upvalue.location()] = value;
+        }
+        Ok(())
+    }
+
+    fn close_upvalue(&mut self) -> RunResult<()> {
+        let last = self.stack().len() - 1;
+        self.close_upvalues(last);
+        self.pop()?;
+        Ok(())
+    }
+
     fn define_global(&mut self) -> RunResult<()> {
         if let Ok(value) = self.pop() {
             let var_name = self.read_string()?;
@@ -142,7 +215,12 @@ impl<W: Write> VM<W> {
     fn get_global(&mut self) -> RunResult<()> {
         let name = self.read_string()?;
 
-        if let Some(value) = self.globals().get(&name).cloned() {
+        let value = self
+            .globals()
+            .get(&name)
+            .or_else(|| self.natives().get(name.as_str()))
+            .cloned();
+        if let Some(value) = value {
             self.push(value);
             return Ok(());
         }
@@ -177,6 +255,314 @@ impl<W: Write> VM<W> {
         Ok(())
     }
 
+    fn range(&mut self) -> RunResult<()> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        self.push(a.range(&b)?);
+        Ok(())
+    }
+
+    /// Advances a for-in loop. The iterated value and the index of the next
+    /// element are on top of the stack, the element is pushed, or the loop
+    /// is left once there are no elements left.
+    fn for_iter(&mut self) -> RunResult<()> {
+        let offset = self.read_short()?;
+
+        let len = self.stack().len();
+        if len < 2 {
+            return Err(RuntimeError::StackEmpty);
+        }
+        let index = match self.stack()[len - 1] {
+            Value::Number(index) => index as usize,
+            _ => return Err(RuntimeError::ArgumentTypes),
+        };
+
+        match self.stack()[len - 2].iteration_element(index)? {
+            Some(element) => {
+                self.stack_mut()[len - 1] = Value::Number((index + 1) as f64);
+                self.push(element);
+            }
+            None => *self.frame_mut()?.ip_mut() += offset as usize,
+        }
+        Ok(())
+    }
+
+    fn build_list(&mut self) -> RunResult<()> {
+        let count = self.read_byte()? as usize;
+        let len = self.stack().len();
+        if len < count {
+            return Err(RuntimeError::StackEmpty);
+        }
+
+        let elements = self.stack_mut().split_off(len - count);
+        let list = self.alloc(List::new(elements));
+        self.push(Value::List(list));
+        Ok(())
+    }
+
+    /// Collects the keys and values on top of the stack, pushed in pairs,
+    /// into a map.
+    fn build_map(&mut self) -> RunResult<()> {
+        let count = self.read_byte()? as usize * 2;
+        let len = self.stack().len();
+        if len < count {
+            return Err(RuntimeError::StackEmpty);
+        }
+
+        let mut map = Map::new();
+        let mut entries = self.stack_mut().split_off(len - count).into_iter();
+        while let (Some(key), Some(value)) = (entries.next(), entries.next()) {
+            map.insert(key, value)?;
+        }
+
+        let map = self.alloc(map);
+        self.push(Value::Map(map));
+        Ok(())
+    }
+
+    fn class(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let count = self.read_byte()?;
+
+        let mut fields = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = self.read_string()?;
+            let mutable = self.read_byte()? == 1;
+            fields.push(Field::new(name, mutable));
+        }
+
+        let class = self.alloc(Class::new(name, fields));
+        self.push(Value::Class(class));
+        Ok(())
+    }
+
+    /// Adds the closure on top of the stack to the class below it.
+    fn method(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let method = match self.pop()? {
+            Value::Closure(closure) => closure,
+            _ => return Err(RuntimeError::ArgumentTypes),
+        };
+
+        match self.pop()? {
+            Value::Class(mut class) => class.add_method(name, method),
+            other => return Err(RuntimeError::InvalidMethodTarget(other.type_name())),
+        }
+        Ok(())
+    }
+
+    fn trait_(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let count = self.read_byte()?;
+
+        let mut methods = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = self.read_string()?;
+            let arity = self.read_byte()?;
+            methods.push((name, arity));
+        }
+
+        let trait_ = self.alloc(Trait::new(name, methods));
+        self.push(Value::Trait(trait_));
+        Ok(())
+    }
+
+    fn enum_(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let count = self.read_byte()?;
+
+        let mut variants = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = self.read_string()?;
+            let field_count = self.read_byte()?;
+            let fields = (0..field_count)
+                .map(|_| self.read_string())
+                .collect::<RunResult<Vec<String>>>()?;
+            variants.push(VariantDef::new(name, fields));
+        }
+
+        let enum_ = self.alloc(Enum::new(name, variants));
+        self.push(Value::Enum(enum_));
+        Ok(())
+    }
+
+    /// Tests whether the value below the enum on the stack is the named
+    /// variant of it. The pattern must name every value the variant carries.
+    fn match_variant(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let count = self.read_byte()?;
+        let enum_ = match self.pop()? {
+            Value::Enum(enum_) => enum_,
+            other => return Err(RuntimeError::NotAnEnum(other.type_name())),
+        };
+        let value = self.pop()?;
+
+        let tag = enum_.tag(&name).ok_or_else(|| {
+            RuntimeError::UndefinedProperty(name.clone(), enum_.name().to_string())
+        })?;
+        let fields = enum_.variants()[tag].fields().len();
+        if fields != count as usize {
+            return Err(RuntimeError::PatternArity(
+                format!("{}.{}", enum_.name(), name),
+                fields,
+                count,
+            ));
+        }
+
+        let matches = match value {
+            Value::Variant(variant) => variant.enum_().ptr_eq(&enum_) && variant.tag() == tag,
+            _ => false,
+        };
+        self.push(Value::Bool(matches));
+        Ok(())
+    }
+
+    /// Tests whether the value is a list of the given length.
+    fn match_list(&mut self) -> RunResult<()> {
+        let len = self.read_byte()? as usize;
+        let matches = match self.pop()? {
+            Value::List(list) => list.len() == len,
+            _ => false,
+        };
+        self.push(Value::Bool(matches));
+        Ok(())
+    }
+
+    /// Replaces a variant with one of the values it carries.
+    fn variant_value(&mut self) -> RunResult<()> {
+        let index = self.read_byte()? as usize;
+        match self.pop()? {
+            Value::Variant(variant) => {
+                let value = variant.values()[index].clone();
+                self.push(value);
+                Ok(())
+            }
+            other => Err(RuntimeError::NotAnEnum(other.type_name())),
+        }
+    }
+
+    fn no_match(&mut self) -> RunResult<()> {
+        let value = self.pop()?;
+        Err(RuntimeError::NoMatch(value.repr()))
+    }
+
+    /// Joins the parts of an interpolated string on top of the stack, each
+    /// part is displayed the way `print` shows it.
+    fn interpolate(&mut self) -> RunResult<()> {
+        let count = self.read_byte()? as usize;
+        let len = self.stack().len();
+        if len < count {
+            return Err(RuntimeError::StackEmpty);
+        }
+
+        let string = self
+            .stack_mut()
+            .split_off(len - count)
+            .iter()
+            .map(|part| part.to_string())
+            .collect();
+        self.push(Value::String(string));
+        Ok(())
+    }
+
+    /// Checks that the class below the trait on the stack implements it.
+    fn impl_(&mut self) -> RunResult<()> {
+        let trait_ = match self.pop()? {
+            Value::Trait(trait_) => trait_,
+            other => return Err(RuntimeError::NotATrait(other.type_name())),
+        };
+
+        match self.pop()? {
+            Value::Class(mut class) => class.implement(trait_),
+            other => Err(RuntimeError::InvalidMethodTarget(other.type_name())),
+        }
+    }
+
+    /// Reads a field of an instance or a value carried by an enum variant,
+    /// binds a method to the instance or class it is accessed on, or looks
+    /// up a variant of an enum.
+    fn get_property(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let target = self.pop()?;
+
+        let (class, field) = match &target {
+            Value::Instance(instance) => (instance.class(), instance.get(&name).cloned()),
+            Value::Class(class) => (*class, None),
+            Value::Enum(enum_) => return self.get_variant(*enum_, name),
+            Value::Variant(variant) => {
+                let value = variant.get(&name).cloned().ok_or_else(|| {
+                    RuntimeError::UndefinedProperty(name, variant.name().to_string())
+                })?;
+                self.push(value);
+                return Ok(());
+            }
+            other => return Err(RuntimeError::NoProperties(name, other.type_name())),
+        };
+
+        if let Some(value) = field {
+            self.push(value);
+            return Ok(());
+        }
+
+        match class.method(&name) {
+            Some(method) => {
+                let bound = self.alloc(BoundMethod::new(target, method));
+                self.push(Value::BoundMethod(bound));
+                Ok(())
+            }
+            None => Err(RuntimeError::UndefinedProperty(
+                name,
+                class.name().to_string(),
+            )),
+        }
+    }
+
+    /// A variant that carries no values is a value itself, any other is
+    /// called with its values to create one.
+    fn get_variant(&mut self, enum_: Gc<Enum>, name: String) -> RunResult<()> {
+        let tag = enum_
+            .tag(&name)
+            .ok_or_else(|| RuntimeError::UndefinedProperty(name, enum_.name().to_string()))?;
+
+        if enum_.variants()[tag].fields().is_empty() {
+            let variant = self.alloc(Variant::new(enum_, tag, vec![]));
+            self.push(Value::Variant(variant));
+        } else {
+            self.push(Value::Constructor(enum_, tag));
+        }
+        Ok(())
+    }
+
+    /// Assigns to a field, leaving the assigned value on the stack.
+    fn set_property(&mut self) -> RunResult<()> {
+        let name = self.read_string()?;
+        let value = self.pop()?;
+
+        match self.pop()? {
+            Value::Instance(mut instance) => instance.set(&name, value.clone())?,
+            other => return Err(RuntimeError::NoProperties(name, other.type_name())),
+        }
+        self.push(value);
+        Ok(())
+    }
+
+    fn get_index(&mut self) -> RunResult<()> {
+        let index = self.pop()?;
+        let target = self.pop()?;
+        self.push(target.index(&index)?);
+        Ok(())
+    }
+
+    /// Assigns to an element, leaving the assigned value on the stack.
+    fn set_index(&mut self) -> RunResult<()> {
+        let value = self.pop()?;
+        let index = self.pop()?;
+        let target = self.pop()?;
+        target.set_index(&index, value.clone())?;
+        self.push(value);
+        Ok(())
+    }
+
     fn loop_(&mut self) -> RunResult<()> {
         let offset = self.read_short()?;
         *self.frame_mut()?.ip_mut() -= offset as usize;
@@ -186,6 +572,7 @@ impl<W: Write> VM<W> {
     fn ret(&mut self) -> RunResult<()> {
         if let Some(frame) = self.frames_mut().pop() {
             let result = self.pop()?;
+            self.close_upvalues(*frame.stack_start());
             self.stack_mut().truncate(*frame.stack_start());
             self.push(result);
             return Ok(());
@@ -195,8 +582,23 @@ impl<W: Write> VM<W> {
     }
 
     fn closure(&mut self) -> RunResult<()> {
-        let closure = Closure::new(self.read_function()?);
-        let clos = self.alloc(closure);
+        let fun = self.read_function()?;
+
+        let mut upvalues = Vec::with_capacity(fun.upvalue_count());
+        for _ in 0..fun.upvalue_count() {
+            let is_local = self.read_byte()? == 1;
+            let index = self.read_byte()? as usize;
+
+            let upvalue = if is_local {
+                let location = self.frame()?.stack_start() + index;
+                self.capture_upvalue(location)
+            } else {
+                self.frame()?.closure().upvalues[index]
+            };
+            upvalues.push(upvalue);
+        }
+
+        let clos = self.alloc(Closure::new(fun, upvalues));
         self.push(Value::Closure(clos));
         Ok(())
     }
@@ -209,7 +611,7 @@ impl<W: Write> VM<W> {
 
     fn print(&mut self) -> RunResult<()> {
         let popped = self.pop()?;
-        writeln!(self.stdout_mut(), "{}", popped);
+        let _ = writeln!(self.stdout_mut(), "{}", popped);
         Ok(())
     }
 }
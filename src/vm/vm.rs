@@ -1,8 +1,9 @@
 use crate::compiler::chunk::Chunk;
-use crate::compiler::object::{Closure, Function};
+use crate::compiler::object::{Class, Closure, Enum, Function, Instance, Native, Upvalue, Variant};
 use crate::compiler::value::Value;
-use crate::vm::error::{RunResult, RuntimeError};
+use crate::vm::error::{RunResult, RuntimeError, TraceFrame, Traceback};
 use crate::vm::frame::CallFrame;
+use crate::vm::native::NATIVES;
 use crate::vm::obj::Gc;
 use std::collections::HashMap;
 use std::io::{stdout, Stdout, Write};
@@ -11,6 +12,8 @@ pub struct VM<W: Write> {
     stack: Vec<Value>,
     frames: Vec<CallFrame>,
     globals: HashMap<String, Value>,
+    natives: HashMap<&'static str, Value>,
+    open_upvalues: Vec<Gc<Upvalue>>,
     stdout: W,
 }
 
@@ -20,21 +23,65 @@ impl VM<Stdout> {
     }
 }
 
+impl Default for VM<Stdout> {
+    fn default() -> Self {
+        VM::new()
+    }
+}
+
 impl<W: Write> VM<W> {
     pub fn with_stdout(stdout: W) -> Self {
         VM {
             stack: Vec::with_capacity(u8::MAX as usize),
             frames: Vec::with_capacity(u8::MAX as usize),
             globals: HashMap::new(),
+            natives: NATIVES
+                .iter()
+                .map(|native| (native.name(), Value::Native(*native)))
+                .collect(),
+            open_upvalues: vec![],
             stdout,
         }
     }
 
-    pub fn interpret(&mut self, fun: Function) -> RunResult<()> {
-        let closure = self.alloc(Closure::new(Gc::new(fun)).clone());
+    /// Runs the script and returns the value it returned. Globals are kept
+    /// afterwards, so the VM can run more scripts in the same session.
+    pub fn interpret(&mut self, fun: Function) -> Result<Value, Traceback> {
+        let closure = self.alloc(Closure::new(Gc::new(fun), vec![]));
         self.push(Value::Closure(closure));
-        self.call_value(0)?;
-        self.run()
+
+        match self.call_value(0).and_then(|_| self.run()) {
+            Ok(_) => Ok(self.stack.pop().unwrap_or(Value::Nil)),
+            Err(error) => {
+                let traceback = self.traceback(error);
+                self.reset();
+                Err(traceback)
+            }
+        }
+    }
+
+    /// Unwinds the stack after a runtime error.
+    fn reset(&mut self) {
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
+
+    /// Captures the current call stack, innermost frame first.
+    fn traceback(&self, error: RuntimeError) -> Traceback {
+        let frames = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                // The ip has already moved past the failing instruction.
+                let offset = frame.ip().saturating_sub(1);
+                let fun = &frame.closure().fun;
+                TraceFrame::new(fun.chunk().line(offset), fun.name().to_string())
+            })
+            .collect();
+
+        Traceback::new(error, frames)
     }
 
     pub fn call_value(&mut self, arity: u8) -> RunResult<()> {
@@ -43,6 +90,14 @@ impl<W: Write> VM<W> {
 
         match callee {
             Value::Closure(c) => self.call(c, arity)?,
+            Value::Native(native) => self.call_native(native, arity)?,
+            Value::Class(class) => self.instantiate(class, arity)?,
+            Value::Constructor(enum_, tag) => self.construct(enum_, tag, arity)?,
+            Value::BoundMethod(bound) => {
+                // The receiver takes the callee's slot, where methods find `self`.
+                self.stack[frame_start] = bound.receiver().clone();
+                self.call(bound.method(), arity)?
+            }
             _ => return Err(RuntimeError::InvalidCallee),
         };
 
@@ -51,7 +106,7 @@ impl<W: Write> VM<W> {
 
     fn call(&mut self, closure: Gc<Closure>, arity: u8) -> RunResult<()> {
         if arity != *closure.fun.arity() {
-            return Err(RuntimeError::IncorrectArity);
+            return Err(RuntimeError::IncorrectArity(*closure.fun.arity(), arity));
         }
 
         let last = self.stack.len();
@@ -61,6 +116,79 @@ impl<W: Write> VM<W> {
         Ok(())
     }
 
+    fn call_native(&mut self, native: Native, arity: u8) -> RunResult<()> {
+        if arity != native.arity() {
+            return Err(RuntimeError::IncorrectArity(native.arity(), arity));
+        }
+
+        let frame_start = self.stack.len() - (arity + 1) as usize;
+        let result = native.call(&self.stack[frame_start + 1..])?;
+        self.stack.truncate(frame_start);
+        self.push(result);
+        Ok(())
+    }
+
+    /// Calling a class creates an instance, the arguments are the values of
+    /// its fields in the order they were declared.
+    fn instantiate(&mut self, class: Gc<Class>, arity: u8) -> RunResult<()> {
+        let count = class.fields().len();
+        if arity as usize != count {
+            return Err(RuntimeError::IncorrectArity(count as u8, arity));
+        }
+
+        let frame_start = self.stack.len() - (arity + 1) as usize;
+        let values = self.stack.split_off(frame_start + 1);
+        self.stack.truncate(frame_start);
+
+        let instance = self.alloc(Instance::new(class, values));
+        self.push(Value::Instance(instance));
+        Ok(())
+    }
+
+    /// Creates a value of the enum variant, the arguments are the values it
+    /// carries.
+    fn construct(&mut self, enum_: Gc<Enum>, tag: usize, arity: u8) -> RunResult<()> {
+        let count = enum_.variants()[tag].fields().len();
+        if arity as usize != count {
+            return Err(RuntimeError::IncorrectArity(count as u8, arity));
+        }
+
+        let frame_start = self.stack.len() - (arity + 1) as usize;
+        let values = self.stack.split_off(frame_start + 1);
+        self.stack.truncate(frame_start);
+
+        let variant = self.alloc(Variant::new(enum_, tag, values));
+        self.push(Value::Variant(variant));
+        Ok(())
+    }
+
+    /// Returns the open upvalue pointing at the stack slot, creating it if
+    /// no closure captured the slot yet.
+    pub fn capture_upvalue(&mut self, location: usize) -> Gc<Upvalue> {
+        if let Some(upvalue) = self.open_upvalues.iter().find(|u| u.location() == location) {
+            return *upvalue;
+        }
+
+        let upvalue = self.alloc(Upvalue::new(location));
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    /// Moves every open upvalue at or above `last` off the stack.
+    pub fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            if upvalue.location() < last {
+                return true;
+            }
+
+            let value = stack[upvalue.location()].clone();
+            let mut upvalue = *upvalue;
+            upvalue.close(value);
+            false
+        });
+    }
+
     pub fn read_string(&mut self) -> RunResult<String> {
         match self.read_constant()? {
             Value::String(s) => Ok(s.clone()),
@@ -70,7 +198,7 @@ impl<W: Write> VM<W> {
 
     pub fn read_function(&mut self) -> RunResult<Gc<Function>> {
         match self.read_constant()? {
-            Value::Function(fun) => Ok(fun.clone()),
+            Value::Function(fun) => Ok(*fun),
             _ => Err(RuntimeError::ArgumentTypes),
         }
     }
@@ -138,6 +266,11 @@ impl<W: Write> VM<W> {
         &self.globals
     }
 
+    /// The built-in functions, looked up when no global has their name.
+    pub fn natives(&self) -> &HashMap<&'static str, Value> {
+        &self.natives
+    }
+
     pub fn globals_mut(&mut self) -> &mut HashMap<String, Value> {
         &mut self.globals
     }
@@ -147,10 +280,6 @@ impl<W: Write> VM<W> {
     }
 
     fn current_chunk(&self) -> RunResult<&Chunk> {
-        Ok(&self.frame()?.closure().fun.chunk())
-    }
-
-    fn current_chunk_mut(&mut self) -> RunResult<&mut Chunk> {
-        Ok(self.frame_mut()?.closure_mut().fun.chunk_mut())
+        Ok(self.frame()?.closure().fun.chunk())
     }
 }